@@ -11,6 +11,7 @@ pub fn main() {
         println!("cargo:rustc-link-search=.");
         println!("cargo:rustc-link-lib=glfw3");
         println!("cargo:rustc-link-lib=webp");
+        println!("cargo:rustc-link-lib=webpdemux");
     } else {
         // unix
         if cfg!(target_os = "freebsd") {
@@ -18,6 +19,7 @@ pub fn main() {
         }
         println!("cargo:rustc-link-lib=glfw");
         println!("cargo:rustc-link-lib=webp");
+        println!("cargo:rustc-link-lib=webpdemux");
     }
 
     // Rerun
@@ -2,7 +2,13 @@ mod scene;
 mod visual;
 
 fn main() {
-    let mut visual = visual::Visual::new();
+    let mut visual = match visual::Visual::new() {
+        Ok(visual) => visual,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     let initial_scene = scene::SceneDummy::new(&mut visual);
     scene::run(initial_scene, visual);
@@ -1,6 +1,20 @@
 use super::visual::Visual;
-use crate::visual::{ModelInstance, Spatial};
+use crate::visual::{
+    Align, AnimatedTexture, AtlasBuilder, Blend, Bloom, Camera, ColorGrading, DynamicResolution,
+    Filter, Fog, FogFalloff, Font, HeightFog, Material, ModelInstance, PostProcessing, Projection,
+    RenderTarget, Sampler, Shadows, Sky, Source, Spatial, Sprite, Text, UniformError, UniformValue,
+    Variable, VariableType, Vignette, Wrap, LAYERS_ALL,
+};
 use std::f32::consts::PI;
+use std::rc::Rc;
+
+const ARRAYS: &[u8] = include_bytes!("scene/penguin/arrays.i16");
+const ELEMENTS: &[u8] = include_bytes!("scene/penguin/elements.u16");
+const TEXTURE: &[u8] = include_bytes!("scene/penguin/penguin.webp");
+// seconds each look of the scene is shown
+const LOOK_DURATION: f32 = 5.;
+// layer of the instances hidden from the minimap
+const LAYER_MONITOR: u32 = 1 << 1;
 
 pub enum Action {
     // Continue with the same scene
@@ -16,10 +30,23 @@ pub trait Scene {
     fn run(&mut self, visual: &mut Visual) -> Action;
 }
 
+// Penguins in front of a row of differently drawn ones, cycling through skies and cameras
 pub struct SceneDummy {
     camera: Spatial,
     time: f32,
+    // frames since the draw statistics were printed
+    #[cfg(debug_assertions)]
+    frames: u32,
     object: ModelInstance,
+    row: Vec<ModelInstance>,
+    // shows the penguin from the side on the monitor and in the corner of the screen
+    target: Rc<RenderTarget>,
+    minimap: Rc<Camera>,
+    skies: [Sky; 3],
+    badge: Rc<AnimatedTexture>,
+    font: Rc<Font>,
+    // index of the look shown, see LOOK_DURATION
+    look: usize,
 }
 
 impl SceneDummy {
@@ -27,16 +54,275 @@ impl SceneDummy {
         println!("Init dummy scene");
         let mut camera = Spatial::new();
         camera.place(0., 0.3, 0.9);
-        let model = visual.load_model(
-            include_bytes!("scene/penguin/arrays.i16"),
-            include_bytes!("scene/penguin/elements.u16"),
-            Some(include_bytes!("scene/penguin/penguin.webp")),
-        );
+        let model = visual.load_model(ARRAYS, ELEMENTS, Some(TEXTURE));
         let object = model.new_instance();
+        object.set_layers(LAYERS_ALL);
+
+        let repeat = Sampler {
+            filter: Filter::Linear,
+            mipmaps: true,
+            wrap: Wrap::Repeat,
+        };
+        let mirror = Sampler {
+            wrap: Wrap::Mirror,
+            ..repeat
+        };
+        let material = model.material();
+        let mut materials = vec![
+            material
+                .with_tint(1., 0.6, 0.6, 0.5)
+                .with_blend(Blend::Transparent),
+            material.with_blend(Blend::Cutout(0.5)),
+            material.with_emissive(0.8, 0.8, 0.2),
+            material
+                .with_texture(0, Some(visual.load_texture(TEXTURE, repeat)))
+                .with_uv_scroll(0.2, 0.),
+            material
+                .with_texture(0, Some(visual.load_texture(TEXTURE, mirror)))
+                .with_uv_scroll(0., -0.2),
+        ];
+        materials.extend(SceneDummy::wobble(visual, &material));
+        let badge = visual.load_animated_texture(TEXTURE, Sampler::default());
+        if badge.frame_count() > 0 {
+            materials.push(material.with_texture(0, Some(badge.texture())));
+        }
+        // the atlas copies draw like the model, but share a texture page
+        let mut atlas = AtlasBuilder::new(64, 1, Sampler::default());
+        for _ in 0..2 {
+            atlas.add(&[(ARRAYS, ELEMENTS, 0.), (ARRAYS, ELEMENTS, 4.)], TEXTURE);
+        }
+        let mut row: Vec<ModelInstance> = materials
+            .into_iter()
+            .map(|material| {
+                let instance = model.new_instance();
+                instance.set_material(Some(material));
+                instance
+            })
+            .collect();
+        for model in visual.load_atlas(atlas) {
+            model.set_material(model.material().with_tint(0.8, 0.9, 1., 1.));
+            row.push(model.new_instance());
+        }
+        for (index, instance) in row.iter().enumerate() {
+            let x = (index as f32 - (row.len() - 1) as f32 / 2.) * 0.4;
+            instance.with_spatial(|spatial| {
+                spatial.place(x, 0., -0.8);
+            });
+        }
+
+        let target = visual.new_render_target(
+            128,
+            128,
+            Sampler {
+                filter: Filter::Linear,
+                ..Sampler::default()
+            },
+        );
+        target.clear_color(0.2, 0.2, 0.3);
+        // the monitor would see itself otherwise
+        let monitor = model.new_instance();
+        monitor.set_material(Some(material.with_texture(0, Some(target.texture()))));
+        monitor.set_layers(LAYER_MONITOR);
+        monitor.with_spatial(|spatial| {
+            spatial.place(-0.6, 0., 0.);
+        });
+        row.push(monitor);
+
+        let grading = Sampler {
+            filter: Filter::Linear,
+            ..Sampler::default()
+        };
+        *visual.post_processing() = PostProcessing {
+            bloom: Some(Bloom::default()),
+            color_grading: Some(ColorGrading {
+                lut: visual.load_texture(include_bytes!("scene/grading/warm.png"), grading),
+                size: 16,
+                strength: 0.5,
+            }),
+            vignette: Some(Vignette::default()),
+            fxaa: true,
+        };
+        let minimap = visual.new_camera();
+        minimap.clear(Some([0.1, 0.1, 0.1]), true);
+        minimap.layers(LAYERS_ALL & !LAYER_MONITOR);
+
+        let skies = [
+            Sky::Gradient {
+                zenith: [0.2, 0.4, 0.8],
+                horizon: [0.6, 0.8, 1.0],
+                ground: [0.3, 0.3, 0.3],
+            },
+            Sky::CubeMap(visual.load_cube_map([TEXTURE; 6], Sampler::default())),
+            Sky::Equirectangular(visual.load_texture(TEXTURE, Sampler::default())),
+        ];
+        // with a sky the fog does not know the background
+        *visual.fog() = Some(Fog {
+            color: Some([0.6, 0.8, 1.0]),
+            falloff: FogFalloff::Exponential { density: 0.3 },
+            height: Some(HeightFog {
+                base: 0.,
+                falloff: 2.,
+            }),
+        });
+        *visual.shadows() = Some(Shadows::default());
+        let mut light = Spatial::new();
+        light.place(1., 2., 1.).spot(&Spatial::new());
+        visual.light(&light, 2., 0.1, 5.);
+        visual.resolution().dynamic = Some(DynamicResolution::default());
+
+        // pixels from the bottom left of the screen, like the default
+        visual.sprite_camera().projection(
+            &Spatial::new(),
+            Projection::PixelPerfect { near: -1., far: 1. },
+        );
+        let font = visual.load_font(
+            include_bytes!("scene/font/pixel.fnt"),
+            &[include_bytes!("scene/font/pixel.png")],
+            Sampler::default(),
+            None,
+        );
+
         SceneDummy {
             camera,
             time: 0.,
+            #[cfg(debug_assertions)]
+            frames: 0,
             object,
+            row,
+            target,
+            minimap,
+            skies,
+            badge,
+            font,
+            look: usize::MAX,
+        }
+    }
+    // Switches the sky and the cameras every few seconds
+    fn change_look(&mut self, visual: &mut Visual) {
+        let look = (self.time / LOOK_DURATION) as usize % self.skies.len();
+        if look == self.look {
+            return;
+        }
+        self.look = look;
+        visual.set_sky(Some(self.skies[look].clone()));
+        self.badge.restart();
+
+        let mut top = Spatial::new();
+        top.place(0., 3., 0.01).spot(&Spatial::new());
+        let projection = Projection::Orthographic {
+            near: 0.1,
+            far: 10.,
+            height: 2.5,
+        };
+        if look == 2 {
+            // only the top view, over the whole screen
+            visual.remove_camera();
+            self.minimap.viewport(0., 0., 1., 1.);
+            self.target.remove_camera();
+        } else {
+            self.minimap.viewport(0.75, 0.75, 0.25, 0.25);
+        }
+        if look == 1 {
+            self.minimap.remove_projection();
+        } else {
+            self.minimap.projection(&top, projection);
+        }
+    }
+    fn draw_overlay(&self, visual: &mut Visual) {
+        let stats = visual.draw_stats();
+        let mut text = Text::new(
+            self.font.clone(),
+            &format!(
+                "DRAWN {} CULLED {} CALLS {}",
+                stats.drawn, stats.culled, stats.calls
+            ),
+        );
+        text.position = [12., 588.];
+        text.size = self.font.line_height() * 2.;
+        text.max_width = Some(160.);
+        text.z = 1.;
+        // dark box behind the text
+        let [width, height] = text.measure();
+        let mut background = Sprite::new(self.badge.texture());
+        background.position = [8., 592.];
+        background.size = [width + 8., height + 8.];
+        background.pivot = [0., 1.];
+        background.tint = [0., 0., 0., 0.5];
+        visual.draw_sprite(background);
+        visual.draw_text(&text);
+
+        let (width, height) = self.target.size();
+        let mut monitor = Sprite::new(self.target.texture());
+        monitor.position = [792., 8.];
+        monitor.size = [width as f32, height as f32];
+        monitor.pivot = [1., 0.];
+        monitor.rotation = (self.time * 0.5).sin() * 0.1;
+        visual.draw_sprite(monitor);
+        let mut caption = Text::new(self.font.clone(), "SIDE VIEW");
+        caption.position = [792., 8. + height as f32 + 16.];
+        caption.size = self.font.line_height() * 2.;
+        caption.align = Align::Right;
+        visual.draw_text(&caption);
+
+        let mut label = Text::new(self.font.clone(), "PENGUIN");
+        label.size = 0.06;
+        label.align = Align::Center;
+        label.color = [1., 1., 0.6, 1.];
+        let mut spatial = Spatial::new();
+        spatial.place(0., 0.45, 0.);
+        visual.draw_text_world(&label, &spatial);
+        if self.badge.frame_count() > 0 {
+            let mut badge = Sprite::new(self.badge.texture());
+            badge.size = [0.1; 2];
+            spatial.place(0., 0.6, 0.).yaw(self.time);
+            visual.draw_sprite_world(badge, &spatial);
+        }
+    }
+    // Materials of the custom program swaying the model, none if it does not compile
+    fn wobble(visual: &mut Visual, material: &Material) -> Vec<Material> {
+        let source = |name: &str, text: &str| {
+            // edited shaders are reloaded in debug builds
+            let path =
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/scene/shaders/").to_string() + name;
+            Source::file(path).unwrap_or_else(|_| Source::new(name, text))
+        };
+        let program = match visual.load_program(
+            &source("wobble.vert", include_str!("scene/shaders/wobble.vert")),
+            &source("wobble.frag", include_str!("scene/shaders/wobble.frag")),
+        ) {
+            Ok(program) => program,
+            Err(_err) => {
+                #[cfg(debug_assertions)]
+                println!("WARNING: {}", _err);
+                return Vec::new();
+            }
+        };
+        // drivers remove uniforms the shaders do not use
+        let stripes = matches!(
+            program.uniforms().get("stripes"),
+            Some(Variable {
+                ty: VariableType::Int,
+                size: 1,
+                ..
+            })
+        ) && program.attributes().contains_key("tex_in");
+        let wobble = material.with_program(program);
+        let with_uniforms = || -> Result<_, UniformError> {
+            let material = wobble.with_uniform("amplitude", UniformValue::Float(0.15))?;
+            if !stripes {
+                return Ok(material);
+            }
+            material
+                .with_uniform("stripes", UniformValue::Int(6))?
+                .with_uniform("stripe_color", UniformValue::Vec4([0.2, 0.4, 1., 0.5]))
+        };
+        match with_uniforms() {
+            Ok(material) => vec![material.clone(), material.with_define("GRAYSCALE")],
+            Err(_err) => {
+                #[cfg(debug_assertions)]
+                println!("WARNING: {}", _err);
+                vec![wobble]
+            }
         }
     }
 }
@@ -48,9 +334,39 @@ impl Scene for SceneDummy {
             spatial.place(0., 0., 0.);
             spatial.yaw(self.time as f32);
         });
+        self.change_look(visual);
+        for (index, instance) in self.row.iter().enumerate() {
+            instance.with_spatial(|spatial| {
+                spatial.yaw(self.time + index as f32);
+            });
+        }
 
         visual.clear(0.6, 0.8, 1.0);
-        visual.camera(&self.camera, 0.1, 100., PI / 2.);
+        if self.look == 1 {
+            let projection = Projection::Orthographic {
+                near: 0.1,
+                far: 100.,
+                height: 1.5,
+            };
+            visual.camera_projection(&self.camera, projection);
+        } else if self.look == 0 {
+            visual.camera(&self.camera, 0.1, 100., PI / 2.);
+        }
+        if self.look != 2 {
+            let mut side = Spatial::new();
+            side.place(1.2, 0.3, 0.).spot(&Spatial::new());
+            self.target.camera(&side, 0.1, 10., PI / 3.);
+        }
+        self.draw_overlay(visual);
+
+        #[cfg(debug_assertions)]
+        {
+            self.frames += 1;
+            if self.frames == 100 {
+                self.frames = 0;
+                println!("{:?}", visual.draw_stats());
+            }
+        }
         Action::Continue
     }
 }
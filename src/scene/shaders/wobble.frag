@@ -0,0 +1,22 @@
+#version 100
+
+precision lowp float;
+
+varying vec2 tex_out;
+
+uniform sampler2D tex;
+uniform vec4 tint;
+uniform vec4 stripe_color;
+uniform int stripes;
+
+// Horizontal stripes of another color over the texture, in gray with GRAYSCALE
+void main() {
+    vec4 color = texture2D(tex, tex_out) * tint;
+    if (stripes > 0 && fract(tex_out.y * float(stripes)) < 0.5) {
+        color.rgb = mix(color.rgb, stripe_color.rgb, stripe_color.a);
+    }
+#ifdef GRAYSCALE
+    color.rgb = vec3(dot(color.rgb, vec3(0.3, 0.59, 0.11)));
+#endif
+    gl_FragColor = color;
+}
@@ -0,0 +1,20 @@
+#version 100
+
+#include "instancing.glsl"
+
+attribute vec4 pos_in;
+attribute vec2 tex_in;
+
+varying vec2 tex_out;
+
+uniform mat4 camera;
+uniform float time;
+uniform float amplitude;
+
+// Sways the model sideways, more the higher the vertex
+void main() {
+    tex_out = vec2(tex_in.x, 1. - tex_in.y);
+    vec4 pos = pos_in;
+    pos.x += sin(time * 3. + pos.y * 4.) * amplitude * pos.y;
+    gl_Position = camera * model_matrix() * pos;
+}
@@ -1,11 +1,33 @@
+mod animation;
+mod atlas;
+mod camera;
+mod etc;
+mod fog;
+mod font;
 mod generated;
 mod gl;
 mod glfw;
+mod image;
+mod inflate;
+mod ktx;
+mod material;
+mod png;
+mod post;
+mod preprocessor;
+mod qoi;
+mod resolution;
+mod shader;
+mod shadow;
+mod sky;
+mod sprite;
 mod vector;
 mod webp;
 
+use crate::visual::image::{Format, Image};
+use crate::visual::ktx::Ktx;
+use crate::visual::resolution::FrameTimer;
 use crate::visual::vector::Vec3;
-use crate::visual::webp::WebP;
+use crate::visual::webp::Animation;
 use gl::Gl;
 use glfw::Glfw;
 use glfw::Window;
@@ -14,7 +36,21 @@ use std::convert::TryInto;
 use std::rc::Rc;
 use vector::Mat4;
 
-use self::gl::Texture;
+pub use self::animation::AnimatedTexture;
+pub use self::atlas::AtlasBuilder;
+pub use self::camera::{Camera, Projection, LAYERS_ALL};
+pub use self::fog::{Fog, FogFalloff, HeightFog};
+pub use self::font::{Align, Font, Text};
+pub use self::gl::{CubeMap, DrawStats, Filter, RenderTarget, Sampler, Texture, Wrap};
+pub use self::material::{Blend, Material};
+pub use self::post::{Bloom, ColorGrading, PostProcessing, Vignette};
+pub use self::resolution::{DynamicResolution, Resolution};
+pub use self::shader::{
+    ShaderError, ShaderProgram, Source, UniformError, UniformValue, Variable, VariableType,
+};
+pub use self::shadow::Shadows;
+pub use self::sky::Sky;
+pub use self::sprite::Sprite;
 
 #[derive(Clone)]
 pub struct Model(Rc<ModelInternal>);
@@ -23,38 +59,72 @@ impl Model {
     pub fn new_instance(&self) -> ModelInstance {
         self.0.new_instance()
     }
+    pub fn material(&self) -> Material {
+        self.0.material()
+    }
+    pub fn set_material(&self, material: Material) {
+        self.0.material.set(Some(material));
+    }
 }
 
 #[derive(Clone)]
-pub struct ModelInstance(Rc<Cell<Spatial>>);
+pub struct ModelInstance(Rc<InstanceInternal>);
+
+pub struct InstanceInternal {
+    spatial: Cell<Spatial>,
+    // overrides the material of the model
+    material: Cell<Option<Material>>,
+    // bit mask, see Camera::layers
+    layers: Cell<u32>,
+}
 
 impl ModelInstance {
     pub fn with_spatial<F>(&self, f: F)
     where
         F: FnOnce(&mut Spatial),
     {
-        let mut spatial = self.0.take();
+        let mut spatial = self.0.spatial.take();
         f(&mut spatial);
-        self.0.set(spatial);
+        self.0.spatial.set(spatial);
+    }
+    pub fn material(&self) -> Option<Material> {
+        let material = self.0.material.take();
+        self.0.material.set(material.clone());
+        material
+    }
+    pub fn set_material(&self, material: Option<Material>) {
+        self.0.material.set(material);
+    }
+    pub fn layers(&self) -> u32 {
+        self.0.layers.get()
+    }
+    // The first layer by default, instances without any layer are never drawn
+    pub fn set_layers(&self, layers: u32) {
+        self.0.layers.set(layers);
     }
 }
 
-pub struct ModelInternal {
+// One level of detail of a model
+pub struct Mesh {
     arrays: Vec<[i16; 6]>,
     elements: Vec<u16>,
 
-    texture: Option<Rc<Texture>>,
-    instances: Cell<Vec<ModelInstance>>,
-    dirty: Cell<bool>,
+    // how many instances are drawn per call, set when the static buffer is rebuilt,
+    // 0 if the mesh did not fit into it
+    batch: Cell<usize>,
+    // camera distance from which on this mesh is used
+    distance: f32,
+    // position in the static element buffer, set when it is rebuilt
+    offset: Cell<usize>,
 }
 
-impl ModelInternal {
-    fn new(arrays_src: &[u8], elements_src: &[u8], texture: Option<Rc<Texture>>) -> ModelInternal {
+impl Mesh {
+    fn new(arrays_src: &[u8], elements_src: &[u8], distance: f32) -> Mesh {
         let arrays_flat: Vec<i16> = arrays_src
             .chunks_exact(2)
             .map(|bytes| i16::from_le_bytes(bytes.try_into().unwrap()))
             .collect();
-        let arrays = arrays_flat
+        let arrays: Vec<[i16; 6]> = arrays_flat
             .chunks_exact(5)
             .map(|shorts| {
                 // because of alignment, we have the first 4 i16 as vertex data (value 0 is for alignment, not read)
@@ -67,16 +137,94 @@ impl ModelInternal {
             .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
             .collect();
 
-        ModelInternal {
+        Mesh {
             arrays,
             elements,
-            texture,
+            batch: Cell::new(0),
+            distance,
+            offset: Cell::new(0),
+        }
+    }
+}
+
+pub struct ModelInternal {
+    // sorted by increasing switch distance, the first one is the most detailed
+    meshes: Vec<Mesh>,
+
+    // bounding sphere in model space, used for frustum culling
+    bounds: (Vec3, f32),
+
+    // always Some, the Option only allows taking it out of the Cell
+    material: Cell<Option<Material>>,
+    instances: Cell<Vec<ModelInstance>>,
+    dirty: Cell<bool>,
+}
+
+impl ModelInternal {
+    fn new(mut meshes: Vec<Mesh>, material: Material) -> ModelInternal {
+        meshes.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        let bounds = ModelInternal::bounding_sphere(&meshes[0].arrays);
+
+        ModelInternal {
+            meshes,
+            bounds,
+            material: Cell::new(Some(material)),
             instances: Cell::new(Vec::new()),
             dirty: Cell::new(true),
         }
     }
+    fn material(&self) -> Material {
+        let material = self.material.take().unwrap();
+        self.material.set(Some(material.clone()));
+        material
+    }
+    fn level(&self, distance: f32) -> usize {
+        self.meshes
+            .iter()
+            .rposition(|mesh| mesh.distance <= distance)
+            .unwrap_or(0)
+    }
+    fn bounding_sphere(arrays: &[[i16; 6]]) -> (Vec3, f32) {
+        let position = |point: &[i16; 6]| {
+            [
+                point[0] as f32 / i16::MAX as f32,
+                point[1] as f32 / i16::MAX as f32,
+                point[2] as f32 / i16::MAX as f32,
+            ]
+        };
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for point in arrays {
+            let xyz = position(point);
+            for i in 0..3 {
+                min[i] = min[i].min(xyz[i]);
+                max[i] = max[i].max(xyz[i]);
+            }
+        }
+        if arrays.is_empty() {
+            return (Vec3::new([0.; 3]), 0.);
+        }
+        let center = [
+            (min[0] + max[0]) / 2.,
+            (min[1] + max[1]) / 2.,
+            (min[2] + max[2]) / 2.,
+        ];
+        let radius = arrays.iter().fold(0., |radius: f32, point| {
+            let xyz = position(point);
+            let dist = ((xyz[0] - center[0]).powi(2)
+                + (xyz[1] - center[1]).powi(2)
+                + (xyz[2] - center[2]).powi(2))
+            .sqrt();
+            radius.max(dist)
+        });
+        (Vec3::new(center), radius)
+    }
     fn new_instance(&self) -> ModelInstance {
-        let instance = ModelInstance(Rc::new(Cell::new(Spatial::new())));
+        let instance = ModelInstance(Rc::new(InstanceInternal {
+            spatial: Cell::new(Spatial::new()),
+            material: Cell::new(None),
+            layers: Cell::new(1),
+        }));
         let mut instances = self.instances.take();
         instances.push(instance.clone());
         self.instances.set(instances);
@@ -93,24 +241,74 @@ pub struct Visual {
 
     models_static: Vec<Model>,
     texture_pool: Vec<Rc<Texture>>,
+    cube_map_pool: Vec<Rc<CubeMap>>,
+    animation_pool: Vec<Rc<AnimatedTexture>>,
+    render_target_pool: Vec<Rc<RenderTarget>>,
+    camera_pool: Vec<Rc<Camera>>,
+    program_pool: Vec<ShaderProgram>,
+
+    post_processing: PostProcessing,
+    resolution: Resolution,
+    frame_timer: FrameTimer,
+    // when the shader sources were last checked for changes
+    #[cfg(debug_assertions)]
+    reload_time: f64,
+    shadows: Option<Shadows>,
+    fog: Option<Fog>,
 }
 
 impl Visual {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, ShaderError> {
         let glfw = Glfw::new();
         let render_size = (800, 600);
         let window = glfw.new_window(render_size.0, render_size.1, "Good day");
         window.make_current();
-        let gl = Gl::new(&glfw);
+        let gl = Gl::new(&glfw)?;
 
-        Visual {
+        Ok(Visual {
             glfw,
             gl,
             window,
             render_size,
             models_static: Vec::new(),
             texture_pool: Vec::new(),
-        }
+            cube_map_pool: Vec::new(),
+            animation_pool: Vec::new(),
+            render_target_pool: Vec::new(),
+            camera_pool: Vec::new(),
+            program_pool: Vec::new(),
+            post_processing: PostProcessing::default(),
+            resolution: Resolution::default(),
+            frame_timer: FrameTimer::default(),
+            #[cfg(debug_assertions)]
+            reload_time: 0.,
+            shadows: None,
+            fog: None,
+        })
+    }
+    // Statistics of the last drawn frame
+    pub fn draw_stats(&self) -> &DrawStats {
+        self.gl.stats()
+    }
+    // Default program with a white tint and no texture
+    pub fn default_material(&self) -> Material {
+        Material::new(self.gl.program_default())
+    }
+    // Effects applied after drawing the scene, all disabled by default
+    pub fn post_processing(&mut self) -> &mut PostProcessing {
+        &mut self.post_processing
+    }
+    // Resolution the scene is drawn at, the full render size by default
+    pub fn resolution(&mut self) -> &mut Resolution {
+        &mut self.resolution
+    }
+    // Shadows of the light, disabled by default
+    pub fn shadows(&mut self) -> &mut Option<Shadows> {
+        &mut self.shadows
+    }
+    // Fog in the default shaders, disabled by default
+    pub fn fog(&mut self) -> &mut Option<Fog> {
+        &mut self.fog
     }
     pub fn should_close(&self) -> bool {
         self.window.should_close()
@@ -119,11 +317,59 @@ impl Visual {
         self.gl.clear_color(r, g, b, 1.0);
         self.gl.clear(true, true);
     }
+    // Drawn behind all models instead of the clear color, respecting the rotation of the camera.
+    // Fog fades into the clear color unless it has its own color, see Fog::color.
+    pub fn set_sky(&mut self, sky: Option<Sky>) {
+        self.gl.set_sky(sky);
+    }
     pub fn camera(&mut self, spatial: &Spatial, near: f32, far: f32, fov: f32) {
-        let aspect = (self.render_size.0 as f32) / (self.render_size.1 as f32);
-        self.gl.set_camera(
+        self.gl.main_camera().perspective(spatial, near, far, fov);
+    }
+    // Same as Visual::camera with an orthographic or pixel-perfect projection instead
+    pub fn camera_projection(&mut self, spatial: &Spatial, projection: Projection) {
+        self.gl.main_camera().projection(spatial, projection);
+    }
+    // Only the additional cameras are drawn until Visual::camera is called again, e.g. for split-screen
+    pub fn remove_camera(&mut self) {
+        self.gl.main_camera().remove_projection();
+    }
+    // Queues the sprite for the next frame, drawn on top of the scene with the sprite camera
+    pub fn draw_sprite(&mut self, sprite: Sprite) {
+        self.gl.queue_sprite(sprite);
+    }
+    // Queues the sprite for the next frame, in the xy plane of the spatial and seen by the main camera only
+    pub fn draw_sprite_world(&mut self, sprite: Sprite, spatial: &Spatial) {
+        self.gl.queue_sprite_world(sprite, spatial.to_mat4());
+    }
+    // Drawn like sprites on top of the scene, in pixels with the default sprite camera
+    pub fn draw_text(&mut self, text: &Text) {
+        for sprite in text.sprites() {
+            self.gl.queue_sprite(sprite);
+        }
+    }
+    // Drawn like sprites in the xy plane of the spatial, in its units
+    pub fn draw_text_world(&mut self, text: &Text, spatial: &Spatial) {
+        let transform = spatial.to_mat4();
+        for sprite in text.sprites() {
+            self.gl.queue_sprite_world(sprite, transform.clone());
+        }
+    }
+    // Pixel-perfect with the origin at the bottom left of the screen by default
+    pub fn sprite_camera(&self) -> &Camera {
+        self.gl.sprite_camera()
+    }
+    // Drawn after the main camera as long as it is referenced, nothing is drawn until its projection is set
+    pub fn new_camera(&mut self) -> Rc<Camera> {
+        let camera = Rc::new(Camera::new());
+        self.camera_pool.push(camera.clone());
+        camera
+    }
+    // Directional light shining along the view direction of the spatial. Shadows are cast by everything
+    // within extent of its axis and between near and far.
+    pub fn light(&mut self, spatial: &Spatial, extent: f32, near: f32, far: f32) {
+        self.gl.set_light(
             Mat4::new()
-                .perspective(near, far, fov, aspect)
+                .orthographic(-extent, extent, -extent, extent, near, far)
                 .mul(spatial.to_mat4_rev()),
         );
     }
@@ -140,6 +386,76 @@ impl Visual {
         });
         texture_pool.shrink_to_fit();
     }
+    fn housekeep_cube_maps(&mut self) {
+        let cube_map_pool = &mut self.cube_map_pool;
+        let gl = &mut self.gl;
+        cube_map_pool.retain(|cube_map| {
+            if Rc::strong_count(cube_map) > 1 {
+                true
+            } else {
+                gl.drop_cube_map(cube_map);
+                false
+            }
+        });
+        cube_map_pool.shrink_to_fit();
+    }
+    // Advances animations as long as the user or a material references them or their texture
+    fn housekeep_animations(&mut self) {
+        let gl = &mut self.gl;
+        let time = self.glfw.time() as f32;
+        self.animation_pool.retain(|animation| {
+            // the texture is also referenced by the texture pool and the animation itself
+            if Rc::strong_count(animation) > 1 || Rc::strong_count(&animation.texture) > 2 {
+                animation.advance(gl, time);
+                true
+            } else {
+                false
+            }
+        });
+    }
+    // Render targets are kept as long as the user or a material references them or their texture
+    fn housekeep_render_targets(&mut self) {
+        let gl = &mut self.gl;
+        self.render_target_pool.retain(|target| {
+            if Rc::strong_count(target) > 1 || Rc::strong_count(&target.texture) > 1 {
+                true
+            } else {
+                gl.drop_render_target(target);
+                false
+            }
+        });
+    }
+    fn housekeep_cameras(&mut self) {
+        self.camera_pool
+            .retain(|camera| Rc::strong_count(camera) > 1);
+    }
+    fn housekeep_programs(&mut self) {
+        let program_pool = &mut self.program_pool;
+        let gl = &mut self.gl;
+        program_pool.retain(|program| {
+            if Rc::strong_count(&program.0) > 1 {
+                true
+            } else {
+                gl.drop_program(program);
+                false
+            }
+        });
+    }
+    // Recompiles programs whose source files changed since they were loaded, checked once a second
+    #[cfg(debug_assertions)]
+    fn reload_programs(&mut self) {
+        let now = self.glfw.time();
+        if now - self.reload_time < 1. {
+            return;
+        }
+        self.reload_time = now;
+        let program_default = self.gl.program_default();
+        self.gl.reload_program(&program_default);
+        self.gl.reload_internal_programs();
+        for program in &self.program_pool {
+            self.gl.reload_program(program);
+        }
+    }
     fn housekeep_models_static(&mut self) -> bool {
         // Implementation is not very optimal, but it is not intended usage to add and remove models that much during execution.
         let mut dirty = false;
@@ -177,30 +493,196 @@ impl Visual {
         }
 
         let dirty = self.housekeep_models_static();
+        self.housekeep_animations();
         self.housekeep_textures();
-        self.gl.draw(&self.render_size, &self.models_static, dirty);
+        self.housekeep_cube_maps();
+        self.housekeep_render_targets();
+        self.housekeep_cameras();
+        self.housekeep_programs();
+        #[cfg(debug_assertions)]
+        self.reload_programs();
+        self.frame_timer
+            .adapt(self.glfw.time(), &mut self.resolution);
+        if dirty {
+            self.gl.upload_static(&self.models_static);
+        }
+        self.gl.set_shadows(self.shadows);
+        self.gl.set_fog(self.fog);
+        self.gl.set_resolution(self.resolution);
+        self.gl.draw(
+            &self.render_size,
+            &self.models_static,
+            &self.render_target_pool,
+            &self.camera_pool,
+            &self.post_processing,
+            self.glfw.time() as f32,
+        );
 
         self.window.swap();
         self.glfw.poll();
     }
+    // The program may use the attributes and uniforms of the default shaders,
+    // e.g. "pos_in", "tex_in", "index_in", "camera", "models[16]", "tint" and "tex"
+    pub fn load_program(
+        &mut self,
+        vert_src: &Source,
+        frag_src: &Source,
+    ) -> Result<ShaderProgram, ShaderError> {
+        let program = self.gl.new_program(vert_src, frag_src)?;
+        self.program_pool.push(program.clone());
+        Ok(program)
+    }
+    // WebP, PNG, QOI and KTX are detected by their magic bytes.
+    // KTX textures stay compressed if the driver supports their format, otherwise they are decompressed.
+    // Falls back to a checkerboard texture if the image cannot be decoded.
+    pub fn load_texture(&mut self, src: &[u8], sampler: Sampler) -> Rc<Texture> {
+        if Format::sniff(src) == Some(Format::Ktx) {
+            if let Some(texture) =
+                Ktx::parse(src).and_then(|ktx| self.gl.new_texture_compressed(&ktx, sampler))
+            {
+                let texture = Rc::new(texture);
+                self.texture_pool.push(texture.clone());
+                return texture;
+            }
+        }
+        match Image::load(src) {
+            Some(image) => self.add_texture(image, sampler),
+            None => {
+                #[cfg(debug_assertions)]
+                println!("WARNING: Could not load texture, using placeholder.");
+                self.gl.texture_missing()
+            }
+        }
+    }
+    // Faces in the order +X, -X, +Y, -Y, +Z, -Z, which have to be square and of the same size and format.
+    // Falls back to checkerboard faces if one of them cannot be decoded.
+    pub fn load_cube_map(&mut self, faces: [&[u8]; 6], sampler: Sampler) -> Rc<CubeMap> {
+        let images: Option<Vec<Image>> = faces.iter().map(|src| Image::load(src)).collect();
+        let images = match images {
+            Some(images)
+                if images.iter().all(|image| {
+                    image.width == image.height
+                        && image.width == images[0].width
+                        && image.alpha == images[0].alpha
+                }) =>
+            {
+                images
+            }
+            _ => {
+                #[cfg(debug_assertions)]
+                println!("WARNING: Could not load cube map, using placeholder.");
+                (0..6).map(|_| gl::checkerboard()).collect()
+            }
+        };
+        let cube_map = Rc::new(self.gl.new_cube_map(&images, sampler));
+        self.cube_map_pool.push(cube_map.clone());
+        cube_map
+    }
+    // Frames are uploaded to the texture as time passes, still images work but never change.
+    // Falls back to a checkerboard texture if the image cannot be decoded.
+    pub fn load_animated_texture(&mut self, src: &[u8], sampler: Sampler) -> Rc<AnimatedTexture> {
+        match webp::decode_animation(src) {
+            Some(animation) => {
+                let first = &animation.frames[0];
+                let mipmaps = sampler.mipmaps
+                    && first.width.is_power_of_two()
+                    && first.height.is_power_of_two();
+                let texture = Rc::new(self.gl.new_texture(&animation.frames[0], sampler));
+                self.texture_pool.push(texture.clone());
+                let animation = Rc::new(AnimatedTexture::new(texture, animation, mipmaps));
+                self.animation_pool.push(animation.clone());
+                animation
+            }
+            None => {
+                #[cfg(debug_assertions)]
+                println!("WARNING: Could not load animated texture, using placeholder.");
+                let animation = Animation {
+                    frames: Vec::new(),
+                    timestamps: Vec::new(),
+                    loop_count: 0,
+                };
+                Rc::new(AnimatedTexture::new(
+                    self.gl.texture_missing(),
+                    animation,
+                    false,
+                ))
+            }
+        }
+    }
+    // Nothing is drawn to the target until its camera is set
+    pub fn new_render_target(
+        &mut self,
+        width: i32,
+        height: i32,
+        sampler: Sampler,
+    ) -> Rc<RenderTarget> {
+        let target = Rc::new(self.gl.new_render_target(width, height, sampler));
+        self.render_target_pool.push(target.clone());
+        target
+    }
     pub fn load_model(
         &mut self,
         arrays_src: &[u8],
         elements_src: &[u8],
         texture_src_option: Option<&[u8]>,
     ) -> Model {
-        let texture = texture_src_option.map(|texture_src| {
-            let webp = WebP::load_rgb(texture_src).expect("Could not load texture");
-            let texture = Rc::new(self.gl.new_texture(webp));
-            self.texture_pool.push(texture.clone());
-            texture
-        });
+        self.load_model_lod(&[(arrays_src, elements_src, 0.)], texture_src_option)
+    }
+    // Every level consists of arrays, elements and the camera distance from which on it is used.
+    // Panics if there are no levels.
+    pub fn load_model_lod(
+        &mut self,
+        levels: &[(&[u8], &[u8], f32)],
+        texture_src_option: Option<&[u8]>,
+    ) -> Model {
+        assert!(!levels.is_empty(), "A model needs at least one mesh");
+        // repeating like GL's default, so UVs outside 0..1 keep tiling
+        let sampler = Sampler {
+            wrap: Wrap::Repeat,
+            ..Sampler::default()
+        };
+        let texture = texture_src_option.map(|texture_src| self.load_texture(texture_src, sampler));
 
-        let model = Model(Rc::new(ModelInternal::new(
-            arrays_src,
-            elements_src,
-            texture,
-        )));
+        let meshes = levels
+            .iter()
+            .map(|(arrays_src, elements_src, distance)| {
+                Mesh::new(arrays_src, elements_src, *distance)
+            })
+            .collect();
+        let material = self.default_material().with_texture(0, texture);
+        self.add_model(meshes, material)
+    }
+    // BMFont text descriptor (.fnt) with one image per page. The pages of distance field fonts
+    // store the distance in alpha over distance_range pixels and want a linear filter.
+    // Falls back to a font without glyphs if the descriptor cannot be parsed.
+    pub fn load_font(
+        &mut self,
+        descriptor: &[u8],
+        pages: &[&[u8]],
+        sampler: Sampler,
+        distance_range: Option<f32>,
+    ) -> Rc<Font> {
+        let pages = pages
+            .iter()
+            .map(|src| self.load_texture(src, sampler))
+            .collect();
+        Rc::new(Font::new(
+            &String::from_utf8_lossy(descriptor),
+            pages,
+            distance_range,
+        ))
+    }
+    // Packs the textures of the models added to the builder into atlas pages
+    pub fn load_atlas(&mut self, atlas: AtlasBuilder) -> Vec<Model> {
+        atlas.build(self)
+    }
+    fn add_texture(&mut self, image: Image, sampler: Sampler) -> Rc<Texture> {
+        let texture = Rc::new(self.gl.new_texture(&image, sampler));
+        self.texture_pool.push(texture.clone());
+        texture
+    }
+    fn add_model(&mut self, meshes: Vec<Mesh>, material: Material) -> Model {
+        let model = Model(Rc::new(ModelInternal::new(meshes, material)));
         self.models_static.push(model.clone());
         model
     }
@@ -213,6 +695,15 @@ impl Drop for Visual {
         texture_pool.iter().for_each(|texture| {
             gl.drop_texture(texture);
         });
+        self.cube_map_pool.iter().for_each(|cube_map| {
+            gl.drop_cube_map(cube_map);
+        });
+        self.render_target_pool.iter().for_each(|target| {
+            gl.drop_render_target(target);
+        });
+        self.program_pool.iter().for_each(|program| {
+            gl.drop_program(program);
+        });
     }
 }
 
@@ -239,10 +730,10 @@ impl Spatial {
         self.xyz = [x, y, z];
         self
     }
-    /*pub fn pitch(&mut self, pitch: f32) -> &mut Self {
+    pub fn pitch(&mut self, pitch: f32) -> &mut Self {
         self.pyr[0] = pitch;
         self
-    }*/
+    }
     pub fn yaw(&mut self, yaw: f32) -> &mut Self {
         self.pyr[1] = yaw;
         self
@@ -250,7 +741,7 @@ impl Spatial {
     /*pub fn roll(&mut self, roll: f32) -> &mut Self {
         self.pyr[2] = roll;
         self
-    }
+    }*/
     pub fn spot(&mut self, other: &Spatial) -> &mut Self {
         self.yaw(f32::atan2(
             self.xyz[0] - other.xyz[0],
@@ -261,7 +752,10 @@ impl Spatial {
             (self.xyz[2] - other.xyz[2]).hypot(self.xyz[0] - other.xyz[0]),
         ));
         self
-    }*/
+    }
+    pub fn position(&self) -> Vec3 {
+        Vec3::new(self.xyz)
+    }
     pub fn to_mat4(&self) -> Mat4 {
         Mat4::new()
             .translate(Vec3::new(self.xyz))
@@ -0,0 +1,70 @@
+use super::gl::{Gl, Texture};
+use super::webp::Animation;
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Texture whose content is replaced by the frames of an animated WebP as time passes,
+// e.g. for flowing lava or blinking screens. Use texture() in materials like any other texture.
+pub struct AnimatedTexture {
+    pub(super) texture: Rc<Texture>,
+    animation: Animation,
+    mipmaps: bool,
+
+    // time the first frame was shown, None until the animation is advanced the first time
+    start: Cell<Option<f32>>,
+    // frame currently uploaded to the texture
+    current: Cell<usize>,
+}
+
+impl AnimatedTexture {
+    pub(super) fn new(
+        texture: Rc<Texture>,
+        animation: Animation,
+        mipmaps: bool,
+    ) -> AnimatedTexture {
+        AnimatedTexture {
+            texture,
+            animation,
+            mipmaps,
+            start: Cell::new(None),
+            current: Cell::new(0),
+        }
+    }
+    pub fn texture(&self) -> Rc<Texture> {
+        self.texture.clone()
+    }
+    pub fn frame_count(&self) -> usize {
+        self.animation.frames.len()
+    }
+    // Starts over from the first frame the next time the animation is advanced
+    pub fn restart(&self) {
+        self.start.set(None);
+    }
+    // Uploads the frame for the given time if it differs from the current one
+    pub(super) fn advance(&self, gl: &mut Gl, time: f32) {
+        if self.animation.frames.len() < 2 {
+            return;
+        }
+        let start = self.start.get().unwrap_or(time);
+        self.start.set(Some(start));
+
+        let frame = self.frame_at(((time - start) * 1000.) as u32);
+        if frame != self.current.replace(frame) {
+            gl.update_texture(&self.texture, &self.animation.frames[frame], self.mipmaps);
+        }
+    }
+    fn frame_at(&self, elapsed: u32) -> usize {
+        let timestamps = &self.animation.timestamps;
+        let duration = timestamps.last().copied().unwrap_or(0).max(1);
+        let loop_count = self.animation.loop_count;
+        if loop_count != 0 && elapsed / duration >= loop_count {
+            // stays at the last frame once all loops are played
+            return timestamps.len() - 1;
+        }
+        let elapsed = elapsed % duration;
+        timestamps
+            .iter()
+            .position(|end| elapsed < *end)
+            .unwrap_or(timestamps.len() - 1)
+    }
+}
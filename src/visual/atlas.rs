@@ -0,0 +1,200 @@
+use super::gl::Sampler;
+use super::image::Image;
+use super::{Mesh, Model, Visual};
+
+// Packs the textures of many models into few atlas pages and rewrites their texture coordinates,
+// so the models can be drawn with a handful of texture binds.
+// Texture coordinates have to lie within [0, 1] since repeating is not possible inside an atlas.
+pub struct AtlasBuilder {
+    page_size: u32,
+    // pixels around every image filled with its border, so filtering does not bleed into neighbours
+    padding: u32,
+    sampler: Sampler,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    meshes: Vec<Mesh>,
+    image: Option<Image>,
+}
+
+// Position of an image inside the atlas
+struct Placement {
+    page: usize,
+    x: u32,
+    y: u32,
+}
+
+impl AtlasBuilder {
+    pub fn new(page_size: u32, padding: u32, sampler: Sampler) -> AtlasBuilder {
+        AtlasBuilder {
+            page_size,
+            padding,
+            sampler,
+            entries: Vec::new(),
+        }
+    }
+    // Same arguments as Visual::load_model_lod, the model is created by build at the returned index.
+    // Panics if there are no levels.
+    pub fn add(&mut self, levels: &[(&[u8], &[u8], f32)], texture_src: &[u8]) -> usize {
+        assert!(!levels.is_empty(), "A model needs at least one mesh");
+        let image = Image::load(texture_src);
+        #[cfg(debug_assertions)]
+        if image.is_none() {
+            println!("WARNING: Could not load texture, using placeholder.");
+        }
+        let meshes = levels
+            .iter()
+            .map(|(arrays_src, elements_src, distance)| {
+                Mesh::new(arrays_src, elements_src, *distance)
+            })
+            .collect();
+        self.entries.push(Entry { meshes, image });
+        self.entries.len() - 1
+    }
+    pub(super) fn build(self, visual: &mut Visual) -> Vec<Model> {
+        let placements = self.pack();
+        let page_count = placements
+            .iter()
+            .flatten()
+            .map(|placement| placement.page + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut pages: Vec<Image> = (0..page_count)
+            .map(|page| {
+                let alpha = self
+                    .entries
+                    .iter()
+                    .zip(&placements)
+                    .any(|(entry, placement)| match (&entry.image, placement) {
+                        (Some(image), Some(placement)) => placement.page == page && image.alpha,
+                        _ => false,
+                    });
+                let channels = if alpha { 4 } else { 3 };
+                Image {
+                    data: vec![0; (self.page_size * self.page_size * channels) as usize],
+                    width: self.page_size,
+                    height: self.page_size,
+                    alpha,
+                }
+            })
+            .collect();
+        for (entry, placement) in self.entries.iter().zip(&placements) {
+            if let (Some(image), Some(placement)) = (&entry.image, placement) {
+                self.blit(&mut pages[placement.page], image, placement);
+            }
+        }
+        let textures: Vec<_> = pages
+            .into_iter()
+            .map(|page| visual.add_texture(page, self.sampler))
+            .collect();
+
+        let page_size = self.page_size as f32;
+        let mut models = Vec::with_capacity(self.entries.len());
+        for (mut entry, placement) in self.entries.into_iter().zip(placements) {
+            let texture = match (&entry.image, placement) {
+                (Some(image), Some(placement)) => {
+                    let scale = [
+                        image.width as f32 / page_size,
+                        image.height as f32 / page_size,
+                    ];
+                    let offset = [
+                        placement.x as f32 / page_size,
+                        placement.y as f32 / page_size,
+                    ];
+                    entry
+                        .meshes
+                        .iter_mut()
+                        .for_each(|mesh| AtlasBuilder::remap(mesh, scale, offset));
+                    textures[placement.page].clone()
+                }
+                _ => visual.gl.texture_missing(),
+            };
+            let material = visual.default_material().with_texture(0, Some(texture));
+            models.push(visual.add_model(entry.meshes, material));
+        }
+        models
+    }
+    // Shelf packing: images sorted by height are put next to each other in rows
+    fn pack(&self) -> Vec<Option<Placement>> {
+        let mut order: Vec<usize> = (0..self.entries.len())
+            .filter(|index| self.entries[*index].image.is_some())
+            .collect();
+        order.sort_by_key(|index| {
+            std::cmp::Reverse(self.entries[*index].image.as_ref().unwrap().height)
+        });
+
+        let mut placements: Vec<Option<Placement>> = self.entries.iter().map(|_| None).collect();
+        let (mut page, mut x, mut y, mut shelf_height) = (0, 0, 0, 0);
+        for index in order {
+            let image = self.entries[index].image.as_ref().unwrap();
+            let width = image.width + 2 * self.padding;
+            let height = image.height + 2 * self.padding;
+            if width > self.page_size || height > self.page_size {
+                #[cfg(debug_assertions)]
+                println!(
+                    "WARNING: Texture of size {}x{} does not fit into an atlas page.",
+                    image.width, image.height
+                );
+                continue;
+            }
+            if x + width > self.page_size {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            if y + height > self.page_size {
+                page += 1;
+                x = 0;
+                y = 0;
+                shelf_height = 0;
+            }
+            placements[index] = Some(Placement {
+                page,
+                x: x + self.padding,
+                y: y + self.padding,
+            });
+            x += width;
+            shelf_height = shelf_height.max(height);
+        }
+        placements
+    }
+    // Copies the image into the page and extends its border pixels into the padding
+    fn blit(&self, page: &mut Image, image: &Image, placement: &Placement) {
+        let page_channels = page.channels();
+        let image_channels = image.channels();
+        let padding = self.padding as i64;
+        for y in -padding..image.height as i64 + padding {
+            for x in -padding..image.width as i64 + padding {
+                let source_x = x.clamp(0, image.width as i64 - 1) as usize;
+                let source_y = y.clamp(0, image.height as i64 - 1) as usize;
+                let source = (source_y * image.width as usize + source_x) * image_channels;
+                let target_x = (placement.x as i64 + x) as usize;
+                let target_y = (placement.y as i64 + y) as usize;
+                let target = (target_y * page.width as usize + target_x) * page_channels;
+                page.data[target..target + 3].copy_from_slice(&image.data[source..source + 3]);
+                if page.alpha {
+                    page.data[target + 3] = if image.alpha {
+                        image.data[source + 3]
+                    } else {
+                        255
+                    };
+                }
+            }
+        }
+    }
+    // Maps texture coordinates of the whole image to its rectangle in the page
+    fn remap(mesh: &mut Mesh, scale: [f32; 2], offset: [f32; 2]) {
+        let max = i16::MAX as f32;
+        for point in &mut mesh.arrays {
+            let u = point[4] as f32 / max;
+            // the shaders flip v, so rows are counted from the bottom
+            let t = 1. - point[5] as f32 / max;
+            let u = offset[0] + u * scale[0];
+            let t = offset[1] + t * scale[1];
+            point[4] = (u * max).round() as i16;
+            point[5] = ((1. - t) * max).round() as i16;
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::Cell;
+
+use crate::visual::vector::{Mat4, Vec3};
+
+use super::Spatial;
+
+// Layers of model instances and cameras that do not set their own
+pub const LAYERS_ALL: u32 = u32::MAX;
+
+// Additional view of the scene drawn into a rectangle of the screen after the main camera,
+// e.g. for split-screen or picture-in-picture. Created with Visual::new_camera and drawn
+// every frame in the order of creation, as long as a projection is set.
+pub struct Camera {
+    // inverse of the spatial and its position
+    view: Cell<Option<(Mat4, Vec3)>>,
+    projection: Cell<Option<Projection>>,
+    // x, y, width and height as fractions of the screen, from the bottom left
+    viewport: Cell<[f32; 4]>,
+    clear_color: Cell<Option<[f32; 3]>>,
+    clear_depth: Cell<bool>,
+    layers: Cell<u32>,
+}
+
+// Distances are along the view direction of the spatial
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    // vertical field of view in radians
+    Perspective { near: f32, far: f32, fov: f32 },
+    // parallel, showing height units vertically around the spatial, e.g. for isometric or side views
+    Orthographic { near: f32, far: f32, height: f32 },
+    // parallel with one unit per pixel of the framebuffer and the spatial at the bottom left, e.g. for UI
+    PixelPerfect { near: f32, far: f32 },
+}
+
+impl Projection {
+    // Size of the viewport in pixels
+    pub(super) fn to_mat4(self, size: (i32, i32)) -> Mat4 {
+        let aspect = size.0 as f32 / size.1.max(1) as f32;
+        match self {
+            Projection::Perspective { near, far, fov } => {
+                Mat4::new().perspective(near, far, fov, aspect)
+            }
+            Projection::Orthographic { near, far, height } => {
+                let (x, y) = (height * aspect / 2., height / 2.);
+                Mat4::new().orthographic(-x, x, -y, y, near, far)
+            }
+            Projection::PixelPerfect { near, far } => {
+                Mat4::new().orthographic(0., size.0 as f32, 0., size.1 as f32, near, far)
+            }
+        }
+    }
+}
+
+impl Camera {
+    pub(super) fn new() -> Camera {
+        Camera {
+            view: Cell::new(None),
+            projection: Cell::new(None),
+            viewport: Cell::new([0., 0., 1., 1.]),
+            clear_color: Cell::new(None),
+            clear_depth: Cell::new(true),
+            layers: Cell::new(LAYERS_ALL),
+        }
+    }
+    // Camera of Visual::camera, cleared by Visual::clear instead
+    pub(super) fn new_main() -> Camera {
+        let camera = Camera::new();
+        camera.clear(None, false);
+        camera
+    }
+    // Same as Visual::camera, the aspect ratio follows the viewport
+    pub fn perspective(&self, spatial: &Spatial, near: f32, far: f32, fov: f32) {
+        self.projection(spatial, Projection::Perspective { near, far, fov });
+    }
+    pub fn projection(&self, spatial: &Spatial, projection: Projection) {
+        self.view
+            .set(Some((spatial.to_mat4_rev(), spatial.position())));
+        self.projection.set(Some(projection));
+    }
+    // The camera is not drawn anymore until a projection is set again
+    pub fn remove_projection(&self) {
+        self.projection.set(None);
+    }
+    // Rectangle as fractions of the screen, from the bottom left
+    pub fn viewport(&self, x: f32, y: f32, width: f32, height: f32) {
+        self.viewport.set([x, y, width, height]);
+    }
+    // What is cleared inside the viewport before drawing, by default only the depth
+    pub fn clear(&self, color: Option<[f32; 3]>, depth: bool) {
+        self.clear_color.set(color);
+        self.clear_depth.set(depth);
+    }
+    // Only instances sharing one of these layers are drawn, see ModelInstance::set_layers
+    pub fn layers(&self, layers: u32) {
+        self.layers.set(layers);
+    }
+    // Viewport in pixels of a framebuffer of this size
+    pub(super) fn viewport_in(&self, size: (i32, i32)) -> (i32, i32, i32, i32) {
+        let [x, y, width, height] = self.viewport.get();
+        let (x0, y0) = (
+            (x * size.0 as f32).round() as i32,
+            (y * size.1 as f32).round() as i32,
+        );
+        let (x1, y1) = (
+            ((x + width) * size.0 as f32).round() as i32,
+            ((y + height) * size.1 as f32).round() as i32,
+        );
+        (x0, y0, (x1 - x0).max(1), (y1 - y0).max(1))
+    }
+    // Combined projection and view with the position, None if no projection is set
+    pub(super) fn matrix(&self, viewport_size: (i32, i32)) -> Option<(Mat4, Vec3)> {
+        let projection = self.projection.get()?;
+        let view = self.view.take();
+        self.view.set(view.clone());
+        let (view, position) = view?;
+        Some((projection.to_mat4(viewport_size).mul(view), position))
+    }
+    pub(super) fn clear_settings(&self) -> (Option<[f32; 3]>, bool) {
+        (self.clear_color.get(), self.clear_depth.get())
+    }
+    pub(super) fn layer_mask(&self) -> u32 {
+        self.layers.get()
+    }
+}
@@ -0,0 +1,423 @@
+use super::image::Image;
+
+// Software decoding of ETC1 and ETC2 textures for drivers that cannot sample them directly.
+// ETC2 is a superset of ETC1, so both are decoded by the same functions.
+
+// Intensity modifiers per table codeword, ordered by pixel index
+const MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+// Distances between the paint colors of the T and H modes
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+// Alpha modifiers of EAC blocks per table index, ordered by pixel index
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// Decodes ETC1 or ETC2 RGB8 data, 8 bytes per 4x4 block
+pub(super) fn decode_rgb(data: &[u8], width: u32, height: u32) -> Option<Image> {
+    decode(data, width, height, false)
+}
+
+// Decodes ETC2 RGBA8 data, 16 bytes per 4x4 block, an EAC alpha block followed by a color block
+pub(super) fn decode_rgba(data: &[u8], width: u32, height: u32) -> Option<Image> {
+    decode(data, width, height, true)
+}
+
+fn decode(data: &[u8], width: u32, height: u32, alpha: bool) -> Option<Image> {
+    let channels = if alpha { 4 } else { 3 };
+    let block_size = if alpha { 16 } else { 8 };
+    let (columns, rows) = (width.div_ceil(4) as usize, height.div_ceil(4) as usize);
+    if data.len() < columns * rows * block_size {
+        return None;
+    }
+
+    let mut image = Image {
+        data: vec![0; width as usize * height as usize * channels],
+        width,
+        height,
+        alpha,
+    };
+    for (index, block) in data
+        .chunks_exact(block_size)
+        .take(columns * rows)
+        .enumerate()
+    {
+        let (alphas, color) = if alpha {
+            (Some(alpha_block(read(&block[0..8]))), read(&block[8..16]))
+        } else {
+            (None, read(block))
+        };
+        let colors = color_block(color);
+        for y in 0..4 {
+            for x in 0..4 {
+                let image_x = (index % columns) * 4 + x;
+                let image_y = (index / columns) * 4 + y;
+                if image_x >= width as usize || image_y >= height as usize {
+                    continue;
+                }
+                let target = (image_y * width as usize + image_x) * channels;
+                image.data[target..target + 3].copy_from_slice(&colors[y * 4 + x]);
+                if let Some(alphas) = &alphas {
+                    image.data[target + 3] = alphas[y * 4 + x];
+                }
+            }
+        }
+    }
+    Some(image)
+}
+
+fn read(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ])
+}
+
+// Value of count bits of the block, from bit high downwards
+fn bits(block: u64, high: u32, count: u32) -> i32 {
+    ((block >> (high + 1 - count)) & ((1 << count) - 1)) as i32
+}
+
+// Pixel index of the 2 bit selectors used by all color modes except planar
+fn selector(block: u64, x: usize, y: usize) -> usize {
+    let i = x * 4 + y;
+    ((((block >> (16 + i)) & 1) << 1) | ((block >> i) & 1)) as usize
+}
+
+fn extend(value: i32, bits: u32) -> i32 {
+    (value << (8 - bits)) | (value >> (2 * bits - 8))
+}
+
+fn clamp(color: [i32; 3]) -> [u8; 3] {
+    color.map(|channel| channel.clamp(0, 255) as u8)
+}
+
+fn offset(color: [i32; 3], offset: i32) -> [u8; 3] {
+    clamp(color.map(|channel| channel + offset))
+}
+
+// Colors of the 4x4 pixels, row by row
+fn color_block(block: u64) -> [[u8; 3]; 16] {
+    let differential = bits(block, 33, 1) == 1;
+    let (first, second) = if differential {
+        let base = [bits(block, 63, 5), bits(block, 55, 5), bits(block, 47, 5)];
+        // 3 bit two's complement
+        let delta = [bits(block, 58, 3), bits(block, 50, 3), bits(block, 42, 3)]
+            .map(|delta| (delta << 29) >> 29);
+        let sum = [base[0] + delta[0], base[1] + delta[1], base[2] + delta[2]];
+        // overflowing sums select the modes added by ETC2
+        if !(0..32).contains(&sum[0]) {
+            return paint_block(block, t_mode(block));
+        } else if !(0..32).contains(&sum[1]) {
+            return paint_block(block, h_mode(block));
+        } else if !(0..32).contains(&sum[2]) {
+            return planar_block(block);
+        }
+        (base.map(|c| extend(c, 5)), sum.map(|c| extend(c, 5)))
+    } else {
+        (
+            [bits(block, 63, 4), bits(block, 55, 4), bits(block, 47, 4)].map(|c| extend(c, 4)),
+            [bits(block, 59, 4), bits(block, 51, 4), bits(block, 43, 4)].map(|c| extend(c, 4)),
+        )
+    };
+
+    // two subblocks of 2x4 pixels side by side, or of 4x2 pixels on top of each other if flipped
+    let flip = bits(block, 32, 1) == 1;
+    let tables = [bits(block, 39, 3), bits(block, 36, 3)];
+    let mut pixels = [[0; 3]; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            let subblock = if flip { y / 2 } else { x / 2 };
+            let color = if subblock == 0 { first } else { second };
+            let modifier = MODIFIERS[tables[subblock] as usize][selector(block, x, y)];
+            pixels[y * 4 + x] = offset(color, modifier);
+        }
+    }
+    pixels
+}
+
+fn t_mode(block: u64) -> [[u8; 3]; 4] {
+    let red = (bits(block, 60, 2) << 2) | bits(block, 57, 2);
+    let first = [red, bits(block, 55, 4), bits(block, 51, 4)].map(|c| extend(c, 4));
+    let second = [bits(block, 47, 4), bits(block, 43, 4), bits(block, 39, 4)].map(|c| extend(c, 4));
+    let distance = DISTANCES[((bits(block, 35, 2) << 1) | bits(block, 32, 1)) as usize];
+    [
+        clamp(first),
+        offset(second, distance),
+        clamp(second),
+        offset(second, -distance),
+    ]
+}
+
+fn h_mode(block: u64) -> [[u8; 3]; 4] {
+    let green = (bits(block, 58, 3) << 1) | bits(block, 52, 1);
+    let blue = (bits(block, 51, 1) << 3) | bits(block, 49, 3);
+    let first = [bits(block, 62, 4), green, blue];
+    let second = [bits(block, 46, 4), bits(block, 42, 4), bits(block, 38, 4)];
+    let value = |c: [i32; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+    let ordered = (value(first) >= value(second)) as i32;
+    let distance =
+        DISTANCES[((bits(block, 34, 1) << 2) | (bits(block, 32, 1) << 1) | ordered) as usize];
+    let (first, second) = (first.map(|c| extend(c, 4)), second.map(|c| extend(c, 4)));
+    [
+        offset(first, distance),
+        offset(first, -distance),
+        offset(second, distance),
+        offset(second, -distance),
+    ]
+}
+
+fn paint_block(block: u64, paint: [[u8; 3]; 4]) -> [[u8; 3]; 16] {
+    let mut pixels = [[0; 3]; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            pixels[y * 4 + x] = paint[selector(block, x, y)];
+        }
+    }
+    pixels
+}
+
+// Colors are interpolated between the origin and the colors at the horizontal and vertical edges
+fn planar_block(block: u64) -> [[u8; 3]; 16] {
+    let origin = [
+        extend(bits(block, 62, 6), 6),
+        extend((bits(block, 56, 1) << 6) | bits(block, 54, 6), 7),
+        extend(
+            (bits(block, 48, 1) << 5) | (bits(block, 44, 2) << 3) | bits(block, 41, 3),
+            6,
+        ),
+    ];
+    let horizontal = [
+        extend((bits(block, 38, 5) << 1) | bits(block, 32, 1), 6),
+        extend(bits(block, 31, 7), 7),
+        extend(bits(block, 24, 6), 6),
+    ];
+    let vertical = [
+        extend(bits(block, 18, 6), 6),
+        extend(bits(block, 12, 7), 7),
+        extend(bits(block, 5, 6), 6),
+    ];
+    let mut pixels = [[0; 3]; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            let color = [0, 1, 2].map(|c| {
+                (x as i32 * (horizontal[c] - origin[c])
+                    + y as i32 * (vertical[c] - origin[c])
+                    + 4 * origin[c]
+                    + 2)
+                    >> 2
+            });
+            pixels[y * 4 + x] = clamp(color);
+        }
+    }
+    pixels
+}
+
+// Alpha values of the 4x4 pixels, row by row
+fn alpha_block(block: u64) -> [u8; 16] {
+    let base = bits(block, 63, 8);
+    let multiplier = bits(block, 55, 4);
+    let table = &EAC_MODIFIERS[bits(block, 51, 4) as usize];
+    let mut alphas = [0; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            let i = x * 4 + y;
+            let index = ((block >> (45 - 3 * i)) & 7) as usize;
+            alphas[y * 4 + x] = (base + table[index] * multiplier).clamp(0, 255) as u8;
+        }
+    }
+    alphas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Block from (highest bit, bit count, value) fields, unset bits are zero
+    fn block(fields: &[(u32, u32, u64)]) -> u64 {
+        fields.iter().fold(0, |block, (high, count, value)| {
+            assert!(*value < 1 << count);
+            block | value << (high + 1 - count)
+        })
+    }
+
+    // Sets the 2 bit pixel index of the pixel at x, y
+    fn with_index(block: u64, x: usize, y: usize, index: u64) -> u64 {
+        let i = x * 4 + y;
+        block | (index >> 1) << (16 + i) | (index & 1) << i
+    }
+
+    #[test]
+    fn individual_mode() {
+        let mut color = block(&[
+            (63, 4, 0xf), // red
+            (59, 4, 0x0),
+            (55, 4, 0x8), // green
+            (51, 4, 0x8),
+            (47, 4, 0x0), // blue
+            (43, 4, 0xf),
+            (39, 3, 0), // tables
+            (36, 3, 7),
+        ]);
+        color = with_index(color, 1, 0, 3);
+        color = with_index(color, 3, 3, 2);
+        let pixels = color_block(color);
+        assert_eq!(pixels[0], [255, 138, 2]);
+        assert_eq!(pixels[1], [247, 128, 0]);
+        assert_eq!(pixels[2], [47, 183, 255]);
+        assert_eq!(pixels[15], [0, 89, 208]);
+    }
+
+    #[test]
+    fn differential_mode_flipped() {
+        let color = block(&[
+            (63, 5, 16), // red and delta +3
+            (58, 3, 3),
+            (55, 5, 0), // green and delta 0
+            (50, 3, 0),
+            (47, 5, 31), // blue and delta -4
+            (42, 3, 4),
+            (39, 3, 1), // tables
+            (36, 3, 2),
+            (33, 1, 1), // differential
+            (32, 1, 1), // flipped
+        ]);
+        let pixels = color_block(color);
+        for x in 0..4 {
+            assert_eq!(pixels[4 + x], [137, 5, 255]);
+            assert_eq!(pixels[8 + x], [165, 9, 231]);
+        }
+    }
+
+    #[test]
+    fn t_mode_paint_colors() {
+        // red 1 with delta -4 overflows
+        let mut color = block(&[
+            (60, 2, 0b01),
+            (58, 1, 1),
+            (57, 2, 0b00),
+            (55, 4, 0xa),
+            (51, 4, 0x5),
+            (47, 4, 0x3),
+            (43, 4, 0xc),
+            (39, 4, 0x6),
+            (35, 2, 0b11), // distance 64
+            (33, 1, 1),
+            (32, 1, 1),
+        ]);
+        for x in 0..4 {
+            color = with_index(color, x, 0, x as u64);
+        }
+        let pixels = color_block(color);
+        assert_eq!(pixels[0], [68, 170, 85]);
+        assert_eq!(pixels[1], [115, 255, 166]);
+        assert_eq!(pixels[2], [51, 204, 102]);
+        assert_eq!(pixels[3], [0, 140, 38]);
+        assert_eq!(pixels[4], [68, 170, 85]);
+    }
+
+    #[test]
+    fn h_mode_paint_colors() {
+        // green 3 with delta -4 overflows, the first color is greater so distance 16 is selected
+        let mut color = block(&[
+            (62, 4, 0x8),
+            (58, 3, 0b010),
+            (52, 1, 1),
+            (51, 1, 1),
+            (50, 1, 1),
+            (49, 3, 0b000),
+            (46, 4, 0x2),
+            (42, 4, 0xe),
+            (38, 4, 0x1),
+            (34, 1, 0),
+            (33, 1, 1),
+            (32, 1, 1),
+        ]);
+        for x in 0..4 {
+            color = with_index(color, x, 0, x as u64);
+        }
+        let pixels = color_block(color);
+        assert_eq!(pixels[0], [152, 101, 152]);
+        assert_eq!(pixels[1], [120, 69, 120]);
+        assert_eq!(pixels[2], [50, 254, 33]);
+        assert_eq!(pixels[3], [18, 222, 1]);
+    }
+
+    #[test]
+    fn planar_mode_gradient() {
+        // blue 0 with delta -4 overflows
+        let color = block(&[
+            (42, 1, 1),
+            (41, 3, 0b001), // blue origin
+            (38, 5, 31),    // red horizontal
+            (33, 1, 1),
+            (32, 1, 1),
+            (12, 7, 127), // green vertical
+            (5, 6, 63),   // blue vertical
+        ]);
+        let pixels = color_block(color);
+        assert_eq!(pixels[0], [0, 0, 4]);
+        assert_eq!(pixels[3], [191, 0, 1]);
+        assert_eq!(pixels[12], [0, 191, 192]);
+        assert_eq!(pixels[15], [191, 191, 189]);
+        assert_eq!(pixels[2 * 4 + 1], [64, 128, 129]);
+    }
+
+    #[test]
+    fn eac_alpha() {
+        let index = |x: u64, y: u64, index: u64| index << (45 - 3 * (x * 4 + y));
+        let alpha = block(&[(63, 8, 128), (55, 4, 2), (51, 4, 13)])
+            | index(0, 0, 7)
+            | index(0, 1, 3)
+            | index(3, 3, 4);
+        let alphas = alpha_block(alpha);
+        assert_eq!(alphas[0], 146);
+        assert_eq!(alphas[4], 108);
+        assert_eq!(alphas[15], 128);
+        assert_eq!(alphas[1], 126);
+
+        let clamped =
+            block(&[(63, 8, 250), (55, 4, 15), (51, 4, 0)]) | index(0, 0, 7) | index(1, 0, 3);
+        let alphas = alpha_block(clamped);
+        assert_eq!(alphas[0], 255);
+        assert_eq!(alphas[1], 25);
+    }
+
+    #[test]
+    fn partial_blocks_and_short_data() {
+        let alpha = block(&[(63, 8, 200)]);
+        let color = block(&[(63, 4, 0xf), (55, 4, 0xf), (47, 4, 0xf)]);
+        let data = [alpha.to_be_bytes(), color.to_be_bytes()].concat();
+        let image = decode_rgba(&data, 2, 3).unwrap();
+        assert_eq!(image.data.len(), 2 * 3 * 4);
+        // modifier +2 of table 0 and alpha modifier -3 of table 0 with multiplier 0
+        assert!(image
+            .data
+            .chunks(4)
+            .all(|pixel| pixel == [255, 255, 255, 200]));
+        assert!(decode_rgba(&data[..15], 2, 3).is_none());
+        assert!(decode_rgb(&data[..8], 5, 1).is_none());
+    }
+}
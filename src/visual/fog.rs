@@ -0,0 +1,70 @@
+use super::shader::UniformValue;
+
+// Fades models into a color with their distance to the camera
+#[derive(Clone, Copy, Debug)]
+pub struct Fog {
+    // the clear color of the view if None, so models disappear into the background.
+    // Views with a sky box need an explicit color close to the horizon of the sky.
+    pub color: Option<[f32; 3]>,
+    pub falloff: FogFalloff,
+    // limits the fog to low areas if set
+    pub height: Option<HeightFog>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FogFalloff {
+    // no fog before start, full fog from end on
+    Linear { start: f32, end: f32 },
+    // the fraction of light passing through decreases by density per unit
+    Exponential { density: f32 },
+}
+
+// Fog below base, thinning out above it
+#[derive(Clone, Copy, Debug)]
+pub struct HeightFog {
+    pub base: f32,
+    // how fast the fog thins out per unit above base
+    pub falloff: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Fog {
+        Fog {
+            color: None,
+            falloff: FogFalloff::Linear {
+                start: 20.,
+                end: 100.,
+            },
+            height: None,
+        }
+    }
+}
+
+impl Fog {
+    pub(super) fn defines(&self) -> Vec<String> {
+        match self.falloff {
+            FogFalloff::Linear { .. } => vec!["FOG".to_string()],
+            FogFalloff::Exponential { .. } => {
+                vec!["FOG".to_string(), "FOG_EXPONENTIAL".to_string()]
+            }
+        }
+    }
+    pub(super) fn uniforms(&self, background: [f32; 3]) -> [(&'static str, UniformValue); 3] {
+        let range = match self.falloff {
+            FogFalloff::Linear { start, end } => [start, end],
+            FogFalloff::Exponential { density } => [density, 0.],
+        };
+        // a falloff of 0 keeps the fog at full height
+        let height = self
+            .height
+            .map_or([0., 0.], |height| [height.base, height.falloff]);
+        [
+            (
+                "fog_color",
+                UniformValue::Vec3(self.color.unwrap_or(background)),
+            ),
+            ("fog_range", UniformValue::Vec2(range)),
+            ("fog_height", UniformValue::Vec2(height)),
+        ]
+    }
+}
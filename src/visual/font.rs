@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::gl::Texture;
+use super::sprite::Sprite;
+
+// Pre-baked font in the text format of BMFont (AngelCode), see Visual::load_font
+pub struct Font {
+    pages: Vec<Rc<Texture>>,
+    // in pixels of the pages
+    line_height: f32,
+    page_size: [f32; 2],
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+    // pixels of the pages over which the distance field goes from 0 to 1 around the edge,
+    // None for bitmap fonts
+    distance_range: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    // x, y, width and height on the page, y from the top
+    rect: [f32; 4],
+    // from the pen position on the top of the line to the top left of the rect
+    offset: [f32; 2],
+    advance: f32,
+    page: usize,
+}
+
+// Horizontal alignment of the lines of a text relative to its position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// Text laid out in lines, drawn with Visual::draw_text or Visual::draw_text_world
+#[derive(Clone)]
+pub struct Text {
+    pub font: Rc<Font>,
+    pub string: String,
+    // top of the first line, at its left end, center or right end depending on the alignment
+    pub position: [f32; 2],
+    // height of a line, the native size of the font by default
+    pub size: f32,
+    // lines are broken at spaces to stay narrower, and inside words that do not fit alone
+    pub max_width: Option<f32>,
+    pub align: Align,
+    pub color: [f32; 4],
+    // see Sprite::z
+    pub z: f32,
+}
+
+impl Font {
+    pub(super) fn new(src: &str, pages: Vec<Rc<Texture>>, distance_range: Option<f32>) -> Font {
+        let mut font = Font {
+            pages,
+            line_height: 1.,
+            page_size: [1.; 2],
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+            distance_range,
+        };
+        if font.parse(src).is_none() {
+            #[cfg(debug_assertions)]
+            println!("WARNING: Could not parse font, text will be empty.");
+            font.glyphs.clear();
+        }
+        font
+    }
+    // Fills in the metrics, None if a required tag or attribute is missing
+    fn parse(&mut self, src: &str) -> Option<()> {
+        let mut common = false;
+        for line in src.lines() {
+            let mut tokens = tokenize(line).into_iter();
+            let tag = match tokens.next() {
+                Some(tag) => tag,
+                None => continue,
+            };
+            let attributes: HashMap<String, String> = tokens
+                .filter_map(|token| {
+                    let (key, value) = token.split_once('=')?;
+                    Some((key.to_string(), value.to_string()))
+                })
+                .collect();
+            let number = |key: &str| attributes.get(key)?.parse::<f32>().ok();
+            let character = |key: &str| char::from_u32(attributes.get(key)?.parse().ok()?);
+            match tag.as_str() {
+                "common" => {
+                    self.line_height = number("lineHeight")?.max(1.);
+                    self.page_size = [number("scaleW")?.max(1.), number("scaleH")?.max(1.)];
+                    common = true;
+                }
+                "char" => {
+                    let glyph = Glyph {
+                        rect: [
+                            number("x")?,
+                            number("y")?,
+                            number("width")?,
+                            number("height")?,
+                        ],
+                        offset: [number("xoffset")?, number("yoffset")?],
+                        advance: number("xadvance")?,
+                        page: number("page").unwrap_or(0.) as usize,
+                    };
+                    if glyph.page >= self.pages.len() {
+                        #[cfg(debug_assertions)]
+                        println!("WARNING: Glyph on missing font page {}.", glyph.page);
+                        continue;
+                    }
+                    self.glyphs.insert(character("id")?, glyph);
+                }
+                "kerning" => {
+                    let pair = (character("first")?, character("second")?);
+                    self.kerning.insert(pair, number("amount")?);
+                }
+                _ => {}
+            }
+        }
+        if common {
+            Some(())
+        } else {
+            None
+        }
+    }
+    // Height of a line in pixels of the pages
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+    // Width of a line of text at the native size, including kerning
+    fn width(&self, line: &[char]) -> f32 {
+        let mut width = 0.;
+        for (index, character) in line.iter().enumerate() {
+            if let Some(glyph) = self.glyphs.get(character) {
+                width += glyph.advance;
+            }
+            if let Some(next) = line.get(index + 1) {
+                width += self.kerning.get(&(*character, *next)).unwrap_or(&0.);
+            }
+        }
+        width
+    }
+    // Splits the string into lines no wider than max_width, all at the native size
+    fn wrap(&self, string: &str, max_width: Option<f32>) -> Vec<Vec<char>> {
+        let mut lines = Vec::new();
+        for paragraph in string.lines() {
+            let max_width = match max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(paragraph.chars().collect());
+                    continue;
+                }
+            };
+            let mut line: Vec<char> = Vec::new();
+            for word in paragraph.split(' ') {
+                let mut candidate = line.clone();
+                if !candidate.is_empty() {
+                    candidate.push(' ');
+                }
+                candidate.extend(word.chars());
+                if line.is_empty() || self.width(&candidate) <= max_width {
+                    line = candidate;
+                } else {
+                    lines.push(line);
+                    line = word.chars().collect();
+                }
+                // words wider than a whole line are broken between characters
+                while line.len() > 1 && self.width(&line) > max_width {
+                    let mut split = 1;
+                    while split < line.len() && self.width(&line[..split + 1]) <= max_width {
+                        split += 1;
+                    }
+                    let rest = line.split_off(split);
+                    lines.push(line);
+                    line = rest;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+impl Text {
+    // White text at the origin with the native size of the font, without wrapping
+    pub fn new(font: Rc<Font>, string: &str) -> Text {
+        Text {
+            size: font.line_height,
+            font,
+            string: string.to_string(),
+            position: [0.; 2],
+            max_width: None,
+            align: Align::Left,
+            color: [1.; 4],
+            z: 0.,
+        }
+    }
+    // Width of the widest line and height of all lines
+    pub fn measure(&self) -> [f32; 2] {
+        let scale = self.size / self.font.line_height;
+        let lines = self
+            .font
+            .wrap(&self.string, self.max_width.map(|width| width / scale));
+        let width = lines
+            .iter()
+            .map(|line| self.font.width(line))
+            .fold(0., f32::max);
+        [width * scale, lines.len() as f32 * self.size]
+    }
+    // One sprite per visible glyph, in the coordinates of the position
+    pub(super) fn sprites(&self) -> Vec<Sprite> {
+        let font = &self.font;
+        let scale = self.size / font.line_height;
+        let [page_width, page_height] = font.page_size;
+        // an edge smoothed over about a pixel when one unit is one pixel
+        let distance_field = font
+            .distance_range
+            .map(|range| 0.5 / (range * scale).max(1e-3));
+        let mut sprites = Vec::new();
+        let lines = font.wrap(&self.string, self.max_width.map(|width| width / scale));
+        for (index, line) in lines.iter().enumerate() {
+            let mut pen = match self.align {
+                Align::Left => 0.,
+                Align::Center => -font.width(line) / 2.,
+                Align::Right => -font.width(line),
+            };
+            let top = -(index as f32) * font.line_height;
+            for (index, character) in line.iter().enumerate() {
+                if let Some(glyph) = font.glyphs.get(character) {
+                    let [x, y, width, height] = glyph.rect;
+                    if width > 0. && height > 0. {
+                        let mut sprite = Sprite::new(font.pages[glyph.page].clone());
+                        sprite.position = [
+                            self.position[0] + (pen + glyph.offset[0]) * scale,
+                            self.position[1] + (top - glyph.offset[1]) * scale,
+                        ];
+                        sprite.size = [width * scale, height * scale];
+                        sprite.pivot = [0., 1.];
+                        sprite.uv = [
+                            x / page_width,
+                            y / page_height,
+                            width / page_width,
+                            height / page_height,
+                        ];
+                        sprite.tint = self.color;
+                        sprite.z = self.z;
+                        sprite.distance_field = distance_field;
+                        sprites.push(sprite);
+                    }
+                    pen += glyph.advance;
+                }
+                if let Some(next) = line.get(index + 1) {
+                    pen += font.kerning.get(&(*character, *next)).unwrap_or(&0.);
+                }
+            }
+        }
+        sprites
+    }
+}
+
+// Splits at spaces outside of double quotes, which are removed
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for character in line.chars() {
+        match character {
+            '"' => quoted = !quoted,
+            ' ' | '\t' if !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            _ => token.push(character),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Rc<Font> {
+        let src = include_str!("../scene/font/pixel.fnt");
+        Rc::new(Font::new(src, vec![Rc::new(Texture(0))], None))
+    }
+
+    #[test]
+    fn metrics() {
+        let font = font();
+        assert_eq!(font.line_height(), 6.);
+        assert_eq!(font.width(&['A', 'B', ' ', 'C']), 16.);
+        assert_eq!(
+            tokenize(r#"page id=0 file="two words.png""#),
+            ["page", "id=0", "file=two words.png"]
+        );
+    }
+
+    #[test]
+    fn wrapped_and_aligned_lines() {
+        let mut text = Text::new(font(), "AB CD EFGHIJ");
+        text.size = 12.;
+        assert_eq!(text.measure(), [96., 12.]);
+        // 20 pixels at the double size fit two glyphs per line, the long word is broken
+        text.max_width = Some(20.);
+        assert_eq!(text.measure(), [16., 60.]);
+        text.align = Align::Right;
+        text.max_width = None;
+        let sprites = text.sprites();
+        assert_eq!(sprites.len(), 10);
+        assert_eq!(sprites[0].position, [-96., 0.]);
+        assert_eq!(sprites[0].size, [6., 10.]);
+    }
+
+    #[test]
+    fn missing_common_tag() {
+        let font = Font::new("char id=65 x=0 y=0 width=3 height=5", Vec::new(), None);
+        assert!(font.glyphs.is_empty());
+    }
+}
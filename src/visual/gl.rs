@@ -1,37 +1,166 @@
 use crate::visual::glfw::Glfw;
-use crate::visual::vector::Mat4;
-use std::ffi::{c_void, CString};
+use crate::visual::vector::{Frustum, Mat4, Vec3};
+use std::cell::Cell;
+use std::ffi::{c_void, CStr};
 use std::mem::size_of;
-use std::ptr::{null, null_mut};
+use std::ptr::null;
+use std::rc::Rc;
+#[cfg(debug_assertions)]
 use std::slice;
 
 use crate::visual::generated::gl;
-use crate::visual::generated::gl::types::{GLchar, GLint, GLuint};
+#[cfg(debug_assertions)]
+use crate::visual::generated::gl::types::GLchar;
+use crate::visual::generated::gl::types::GLint;
 use crate::visual::generated::gl::Gles2;
 
-use super::webp::WebP;
-use super::Model;
+use super::camera::{Camera, LAYERS_ALL};
+use super::fog::Fog;
+use super::image::Image;
+use super::ktx::{self, Compression, Ktx};
+use super::material::{Blend, Material};
+use super::post::{PostChain, PostProcessing};
+use super::resolution::Resolution;
+use super::shader::{Linked, ShaderError, ShaderProgram, Source, UniformValue, SAMPLERS};
+use super::shadow::{ShadowMap, Shadows};
+use super::sky::{Sky, SkyBox};
+use super::sprite::{Sprite, SpriteBatch};
+use super::{Model, ModelInternal, Spatial};
+
+// Size of the "models" uniform array, passed to the shaders as a define
+pub const BATCH_SIZE_MAX: usize = 16;
+// Upper bound of vertices a model may occupy in the static buffer after being replicated for batching
+const BATCH_VERTICES_MAX: usize = 2048;
+// Elements are 16 bit, so the static buffer can address no more vertices
+const STATIC_VERTICES_MAX: usize = 1 << 16;
+
+// Number of copies of a mesh that are stored in the static buffer, i.e. how many instances can be drawn with a single call
+fn batch_size(vertex_count: usize) -> usize {
+    (BATCH_VERTICES_MAX / vertex_count.max(1)).clamp(1, BATCH_SIZE_MAX)
+}
+
 pub struct Gl {
     gl: Gles2,
-    programs: Programs,
+    program_default: ShaderProgram,
+    // bound for models without texture
+    texture_white: Texture,
+    texture_missing: Rc<Texture>,
 
     vao_static: Vao,
 
     arrays_static: Buffer,
     elements_static: Buffer,
 
+    // of the view being drawn
     camera: Mat4,
+    // covers the whole screen, drawn before the additional cameras
+    main_camera: Rc<Camera>,
     light: Mat4,
+
+    camera_position: Vec3,
+    frustum: Frustum,
+    // texture of the render target being drawn into, 0 for the screen
+    target_texture: u32,
+    stats: DrawStats,
+    compressions: Compressions,
+    post: PostChain,
+    shadow: ShadowMap,
+    sky: SkyBox,
+    sprites: SpriteBatch,
+    fog: Option<Fog>,
+    resolution: Resolution,
+    clear_color: [f32; 4],
+    // clear color of the view being drawn, the default fog color
+    background: [f32; 3],
+}
+
+// Compressed texture formats the driver can sample directly, detected when Gl is created
+#[derive(Debug)]
+struct Compressions {
+    // GL_OES_compressed_ETC1_RGB8_texture
+    etc1: bool,
+    // part of GLES3, ETC1 textures can be uploaded as ETC2 since it is a superset
+    etc2: bool,
+}
+
+impl Compressions {
+    fn detect(gl: &Gles2) -> Compressions {
+        let string = |name| unsafe {
+            let string = gl.GetString(name);
+            if string.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(string as *const _)
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        };
+        let extensions = string(gl::EXTENSIONS);
+        let compressions = Compressions {
+            etc1: extensions
+                .split_whitespace()
+                .any(|extension| extension == "GL_OES_compressed_ETC1_RGB8_texture"),
+            etc2: string(gl::VERSION).starts_with("OpenGL ES 3"),
+        };
+        #[cfg(debug_assertions)]
+        println!("Texture compression support: {:?}", compressions);
+        compressions
+    }
+    fn internal_format(&self, compression: Compression) -> Option<u32> {
+        match compression {
+            Compression::Etc1 if self.etc1 => Some(ktx::ETC1_RGB8_OES),
+            Compression::Etc1 | Compression::Etc2Rgb if self.etc2 => {
+                Some(ktx::COMPRESSED_RGB8_ETC2)
+            }
+            Compression::Etc2Rgba if self.etc2 => Some(ktx::COMPRESSED_RGBA8_ETC2_EAC),
+            _ => None,
+        }
+    }
+}
+
+// Instances of one model sharing the same material, with the model matrices per level of detail
+struct DrawGroup<'a> {
+    model: &'a ModelInternal,
+    // variant of the material's program
+    linked: Rc<Linked>,
+    material: Material,
+    levels: Vec<Vec<f32>>,
+}
+
+impl<'a> DrawGroup<'a> {
+    fn new(model: &'a ModelInternal, linked: Rc<Linked>, material: Material) -> DrawGroup<'a> {
+        DrawGroup {
+            model,
+            linked,
+            material,
+            levels: vec![Vec::new(); model.meshes.len()],
+        }
+    }
+}
+
+// Program and textures currently bound, to avoid redundant state changes
+#[derive(Default)]
+struct Bound {
+    program: Option<u32>,
+    textures: Vec<u32>,
+}
+
+// Statistics about the most recent call of Gl::draw, the instances are those of the main camera
+#[derive(Debug, Default)]
+pub struct DrawStats {
+    pub drawn: usize,
+    pub culled: usize,
+    pub calls: usize,
 }
 
 impl Gl {
-    pub fn new(glfw: &Glfw) -> Gl {
+    pub fn new(glfw: &Glfw) -> Result<Gl, ShaderError> {
         let loadfn = glfw.get_loadfn();
         let gl = Gles2::load_with(loadfn);
 
         #[cfg(debug_assertions)]
         unsafe {
-            gl.DebugMessageCallbackKHR(Some(debug_callback), null_mut());
+            gl.DebugMessageCallbackKHR(Some(debug_callback), std::ptr::null_mut());
             gl.Enable(gl::DEBUG_OUTPUT);
         }
 
@@ -41,6 +170,13 @@ impl Gl {
             gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
         }
 
+        // created first so nothing else leaks if it fails
+        let program_default = ShaderProgram::new(
+            &gl,
+            &Source::builtin("default.vert", include_str!("shaders/default.vert")),
+            &Source::builtin("default.frag", include_str!("shaders/default.frag")),
+        )?;
+
         let vao_static = Vao::new(&gl);
         let arrays_static = Buffer::new(&gl);
         let elements_static = Buffer::new(&gl);
@@ -50,28 +186,87 @@ impl Gl {
             gl.BindBuffer(gl::ARRAY_BUFFER, arrays_static.0);
             gl.EnableVertexAttribArray(0);
             gl.EnableVertexAttribArray(1);
+            gl.EnableVertexAttribArray(2);
             gl.VertexAttribPointer(0, 3, gl::SHORT, gl::TRUE, 12, null());
             gl.VertexAttribPointer(1, 2, gl::SHORT, gl::TRUE, 12, 8 as *const c_void);
+            // the alignment slot holds the index into the "models" uniform array
+            gl.VertexAttribPointer(2, 1, gl::SHORT, gl::FALSE, 12, 6 as *const c_void);
 
             gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, elements_static.0);
         }
 
-        let programs = Programs::new(&gl);
+        let post = match PostChain::new(&gl) {
+            Ok(post) => post,
+            Err(err) => {
+                program_default.drop_with_gl(&gl);
+                return Err(err);
+            }
+        };
+        let shadow = match ShadowMap::new(&gl) {
+            Ok(shadow) => shadow,
+            Err(err) => {
+                program_default.drop_with_gl(&gl);
+                let mut post = post;
+                post.drop_with_gl(&gl);
+                return Err(err);
+            }
+        };
+        let sky = match SkyBox::new(&gl) {
+            Ok(sky) => sky,
+            Err(err) => {
+                program_default.drop_with_gl(&gl);
+                let (mut post, mut shadow) = (post, shadow);
+                post.drop_with_gl(&gl);
+                shadow.drop_with_gl(&gl);
+                return Err(err);
+            }
+        };
+        let sprites = match SpriteBatch::new(&gl) {
+            Ok(sprites) => sprites,
+            Err(err) => {
+                program_default.drop_with_gl(&gl);
+                let (mut post, mut shadow, mut sky) = (post, shadow, sky);
+                post.drop_with_gl(&gl);
+                shadow.drop_with_gl(&gl);
+                sky.drop_with_gl(&gl);
+                return Err(err);
+            }
+        };
+        let compressions = Compressions::detect(&gl);
+        let texture_white = Texture::new_white(&gl);
+        let texture_missing = Rc::new(Texture::new_missing(&gl));
 
         unsafe {
             gl.Enable(gl::DEPTH_TEST);
         }
-        Gl {
+        Ok(Gl {
             gl,
-            programs,
+            program_default,
+            texture_white,
+            texture_missing,
             vao_static,
             arrays_static,
             elements_static,
             camera: Mat4::new(),
+            main_camera: Rc::new(Camera::new_main()),
             light: Mat4::new(),
-        }
+            camera_position: Vec3::new([0.; 3]),
+            frustum: Frustum::new(&Mat4::new()),
+            target_texture: 0,
+            stats: DrawStats::default(),
+            compressions,
+            post,
+            shadow,
+            sky,
+            sprites,
+            fog: None,
+            resolution: Resolution::default(),
+            clear_color: [0.; 4],
+            background: [0.; 3],
+        })
     }
-    pub fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
+    pub fn clear_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.clear_color = [r, g, b, a];
         unsafe {
             self.gl.ClearColor(r, g, b, a);
         }
@@ -83,145 +278,643 @@ impl Gl {
             self.gl.Clear(buffer_bit);
         }
     }
-    pub fn set_camera(&mut self, camera: Mat4) {
-        self.camera = camera;
+    pub fn main_camera(&self) -> &Camera {
+        &self.main_camera
+    }
+    pub fn sprite_camera(&self) -> &Camera {
+        &self.sprites.camera
+    }
+    // Drawn with the next call of Gl::draw
+    pub fn queue_sprite(&mut self, sprite: Sprite) {
+        self.sprites.queue(sprite);
+    }
+    pub fn queue_sprite_world(&mut self, sprite: Sprite, transform: Mat4) {
+        self.sprites.queue_world(sprite, transform);
     }
+    // View and projection of the directional light
+    pub fn set_light(&mut self, light: Mat4) {
+        self.light = light;
+    }
+    pub fn set_shadows(&mut self, shadows: Option<Shadows>) {
+        self.shadow.configure(&self.gl, shadows);
+    }
+    pub fn set_sky(&mut self, sky: Option<Sky>) {
+        self.sky.sky = sky;
+    }
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog;
+    }
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+    }
+    // The shadow map and render targets are drawn first, so they are up to date when drawing to the screen.
+    // The additional cameras are drawn after the main one, into the same image,
+    // and the sprites on top after post-processing.
+    // Sprites in the world are only seen by the main camera.
     pub fn draw(
         &mut self,
         render_size: &(i32, i32),
         models_static: &[Model],
-        models_static_dirty: bool,
+        render_targets: &[Rc<RenderTarget>],
+        cameras: &[Rc<Camera>],
+        post_processing: &PostProcessing,
+        time: f32,
     ) {
-        unsafe {
-            self.gl.Viewport(0, 0, render_size.0, render_size.1);
+        self.stats = DrawStats::default();
+        let [r, g, b, _] = self.clear_color;
+        self.background = [r, g, b];
+        // the shadow pass picks the levels of detail seen by the main camera
+        if let Some((_, position)) = self.main_camera.matrix(*render_size) {
+            self.camera_position = position;
+        }
+        self.draw_shadow_map(models_static, time);
+        for target in render_targets {
+            self.draw_target(target, models_static, time);
+        }
+        let post = self
+            .post
+            .begin(&self.gl, post_processing, &self.resolution, *render_size);
+        let size = if post {
+            self.resolution.size(*render_size)
+        } else {
+            unsafe {
+                self.gl.Viewport(0, 0, render_size.0, render_size.1);
+            }
+            *render_size
+        };
+        let main_camera = self.main_camera.clone();
+        // only the instances of the main camera are counted, the other views draw them again
+        (self.stats.drawn, self.stats.culled) =
+            self.draw_camera(&main_camera, size, models_static, time);
+        self.sprites
+            .draw_world(&self.gl, &main_camera, size, &mut self.stats);
+        for camera in cameras {
+            self.draw_camera(camera, size, models_static, time);
+        }
+        if post {
+            self.post
+                .finish(&self.gl, post_processing, &self.resolution);
         }
+        // on the screen at full resolution, unaffected by the effects
+        self.sprites.draw(&self.gl, *render_size, &mut self.stats);
+    }
+    // Replaces the contents of the static buffers, needed before drawing when the static models changed
+    pub fn upload_static(&mut self, models_static: &[Model]) {
         if models_static.is_empty() {
             return;
         }
         unsafe {
-            self.gl.UseProgram(self.programs.default.0);
-            self.gl
-                .UniformMatrix4fv(self.programs.camera, 1, gl::FALSE, self.camera.as_ptr());
-            self.gl
-                .UniformMatrix4fv(self.programs.light, 1, gl::FALSE, self.light.as_ptr());
+            // the element buffer binding is part of the VAO
+            self.gl.BindVertexArrayOES(self.vao_static.0);
         }
-        if models_static_dirty {
-            let (arrays_data_len, elements_data_len) =
-                models_static
-                    .iter()
-                    .fold((0, 0), |(arrays_size, elements_size), model| {
-                        (
-                            arrays_size + model.0.arrays.len() * 6,
-                            elements_size + model.0.elements.len(),
-                        )
-                    });
-            let mut arrays_data = Vec::with_capacity(arrays_data_len);
-            let mut elements_data = Vec::with_capacity(elements_data_len);
-            models_static.iter().for_each(|model| {
-                let internal = &model.0;
+        let meshes = models_static.iter().flat_map(|model| &model.0.meshes);
+        // without room for the copies every instance is drawn on its own
+        let batched = meshes
+            .clone()
+            .map(|mesh| mesh.arrays.len() * batch_size(mesh.arrays.len()))
+            .sum::<usize>()
+            <= STATIC_VERTICES_MAX;
+        #[cfg(debug_assertions)]
+        if !batched {
+            println!("WARNING: Static models too large for batching.");
+        }
+        let mut vertices = 0;
+        for mesh in meshes.clone() {
+            let batch = if batched {
+                batch_size(mesh.arrays.len())
+            } else {
+                1
+            };
+            if vertices + mesh.arrays.len() * batch > STATIC_VERTICES_MAX {
+                #[cfg(debug_assertions)]
+                println!("WARNING: Static buffer full, mesh is not drawn.");
+                mesh.batch.set(0);
+            } else {
+                vertices += mesh.arrays.len() * batch;
+                mesh.batch.set(batch);
+            }
+        }
+        let (arrays_data_len, elements_data_len) =
+            meshes
+                .clone()
+                .fold((0, 0), |(arrays_size, elements_size), mesh| {
+                    (
+                        arrays_size + mesh.arrays.len() * 6 * mesh.batch.get(),
+                        elements_size + mesh.elements.len() * mesh.batch.get(),
+                    )
+                });
+        let mut arrays_data = Vec::with_capacity(arrays_data_len);
+        let mut elements_data = Vec::with_capacity(elements_data_len);
+        meshes.for_each(|mesh| {
+            mesh.offset.set(elements_data.len());
+            for copy in 0..mesh.batch.get() {
                 let offset = (arrays_data.len() / 6) as u16; // offset that must be added to the element index
-                internal
-                    .arrays
-                    .iter()
-                    .for_each(|point| arrays_data.extend_from_slice(point));
-                internal
-                    .elements
+                mesh.arrays.iter().for_each(|point| {
+                    arrays_data.extend_from_slice(point);
+                    let len = arrays_data.len();
+                    arrays_data[len - 3] = copy as i16;
+                });
+                mesh.elements
                     .iter()
                     .for_each(|index| elements_data.push(index + offset));
-            });
+            }
+        });
+        unsafe {
+            self.gl.BindBuffer(gl::ARRAY_BUFFER, self.arrays_static.0);
+            self.gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (arrays_data_len * size_of::<i16>()) as isize,
+                arrays_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            // ELEMENTS_BUFFER should already be bound because of VAO
+            self.gl
+                .BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.elements_static.0);
+            self.gl.BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                (elements_data_len * size_of::<u16>()) as isize,
+                elements_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+        }
+    }
+    fn draw_target(&mut self, target: &RenderTarget, models_static: &[Model], time: f32) {
+        let (mut camera, mut position) = match target.camera.take() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let mut frustum = Frustum::new(&camera);
+        std::mem::swap(&mut self.camera, &mut camera);
+        std::mem::swap(&mut self.camera_position, &mut position);
+        std::mem::swap(&mut self.frustum, &mut frustum);
+        self.target_texture = target.texture.0;
+        let background = std::mem::replace(&mut self.background, target.clear_color.get());
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+            self.gl.Viewport(0, 0, target.size.0, target.size.1);
+            let [r, g, b] = self.background;
+            self.gl.ClearColor(r, g, b, 1.0);
+            self.gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        self.draw_view(models_static, time, false, LAYERS_ALL);
+        self.target_texture = 0;
+        self.background = background;
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            let [r, g, b, a] = self.clear_color;
+            self.gl.ClearColor(r, g, b, a);
+            if target.mipmaps {
+                self.gl.BindTexture(gl::TEXTURE_2D, target.texture.0);
+                self.gl.GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+        std::mem::swap(&mut self.camera, &mut camera);
+        std::mem::swap(&mut self.camera_position, &mut position);
+        std::mem::swap(&mut self.frustum, &mut frustum);
+        target.camera.set(Some((camera, position)));
+    }
+    // Draws the depth of the models as seen from the light, see use_program for sampling it
+    fn draw_shadow_map(&mut self, models_static: &[Model], time: f32) {
+        let (framebuffer, size) = match self.shadow.target(&self.gl) {
+            Some(target) => (target.framebuffer, target.size),
+            None => return,
+        };
+        // the light is not needed by the shadow pass, so it can hold the camera meanwhile
+        let mut frustum = Frustum::new(&self.light);
+        std::mem::swap(&mut self.camera, &mut self.light);
+        std::mem::swap(&mut self.frustum, &mut frustum);
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            self.gl.Viewport(0, 0, size.0, size.1);
+            self.gl.ClearColor(1., 1., 1., 1.);
+            self.gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        self.draw_view(models_static, time, true, LAYERS_ALL);
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            let [r, g, b, a] = self.clear_color;
+            self.gl.ClearColor(r, g, b, a);
+        }
+        std::mem::swap(&mut self.camera, &mut self.light);
+        std::mem::swap(&mut self.frustum, &mut frustum);
+    }
+    // Draws the view of the camera into its part of the framebuffer of this size, counted like draw_view
+    fn draw_camera(
+        &mut self,
+        camera: &Camera,
+        size: (i32, i32),
+        models_static: &[Model],
+        time: f32,
+    ) -> (usize, usize) {
+        let (x, y, width, height) = camera.viewport_in(size);
+        let (mut matrix, mut position) = match camera.matrix((width, height)) {
+            Some(camera) => camera,
+            None => return (0, 0),
+        };
+        let mut frustum = Frustum::new(&matrix);
+        std::mem::swap(&mut self.camera, &mut matrix);
+        std::mem::swap(&mut self.camera_position, &mut position);
+        std::mem::swap(&mut self.frustum, &mut frustum);
+        let (clear_color, clear_depth) = camera.clear_settings();
+        // cameras that do not clear are drawn over the background of the previous ones
+        let background = self.background;
+        self.background = clear_color.unwrap_or(background);
+        unsafe {
+            self.gl.Viewport(x, y, width, height);
+        }
+        if clear_color.is_some() || clear_depth {
             unsafe {
-                self.gl.BindBuffer(gl::ARRAY_BUFFER, self.arrays_static.0);
-                self.gl.BufferData(
-                    gl::ARRAY_BUFFER,
-                    (arrays_data_len * size_of::<i16>()) as isize,
-                    arrays_data.as_ptr() as *const _,
-                    gl::STATIC_DRAW,
-                );
-                // ELEMENTS_BUFFER should already be bound because of VAO
-                self.gl
-                    .BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.elements_static.0);
-                self.gl.BufferData(
-                    gl::ELEMENT_ARRAY_BUFFER,
-                    (elements_data_len * size_of::<u16>()) as isize,
-                    elements_data.as_ptr() as *const _,
-                    gl::STATIC_DRAW,
-                );
+                // clearing ignores the viewport
+                self.gl.Enable(gl::SCISSOR_TEST);
+                self.gl.Scissor(x, y, width, height);
+                if let Some([r, g, b]) = clear_color {
+                    self.gl.ClearColor(r, g, b, 1.0);
+                    self.gl.Clear(gl::COLOR_BUFFER_BIT);
+                    let [r, g, b, a] = self.clear_color;
+                    self.gl.ClearColor(r, g, b, a);
+                }
+                if clear_depth {
+                    self.gl.Clear(gl::DEPTH_BUFFER_BIT);
+                }
+                self.gl.Disable(gl::SCISSOR_TEST);
             }
         }
+        let counts = self.draw_view(models_static, time, false, camera.layer_mask());
+        self.background = background;
+        std::mem::swap(&mut self.camera, &mut matrix);
+        std::mem::swap(&mut self.camera_position, &mut position);
+        std::mem::swap(&mut self.frustum, &mut frustum);
+        counts
+    }
+    // Draws the models with the current camera into the bound framebuffer, if they share one of the layers.
+    // The shadow pass draws the packed depth of everything except transparent models.
+    // Returns how many instances were drawn and culled.
+    fn draw_view(
+        &mut self,
+        models_static: &[Model],
+        time: f32,
+        shadow_pass: bool,
+        layers: u32,
+    ) -> (usize, usize) {
         unsafe {
             self.gl.BindVertexArrayOES(self.vao_static.0);
         }
-        let mut offset = 0;
+
+        // Collect the visible instances per model and material, then draw sorted by material.
+        // Transparent instances are drawn afterwards, sorted back to front.
+        let mut groups: Vec<DrawGroup> = Vec::new();
+        let mut transparent: Vec<(f32, &ModelInternal, Material, usize, Mat4)> = Vec::new();
+        let mut variants: Vec<(Material, Rc<Linked>)> = Vec::new();
+        let (mut drawn, mut culled) = (0, 0);
         for model in models_static {
-            let count = model.0.elements.len() as i32;
-            match &model.0.texture {
-                Some(texture) => unsafe {
-                    self.gl.ActiveTexture(gl::TEXTURE0);
-                    self.gl.BindTexture(gl::TEXTURE_2D, texture.0);
-                },
-                None => {} // TODO: Bind dummy texture
-            }
+            let first = groups.len();
+            let model_material = model.0.material();
+            let (center, radius) = &model.0.bounds;
             let instances = model.0.instances.take();
             for instance in &instances {
-                instance.with_spatial(|spatial| unsafe {
-                    self.gl.UniformMatrix4fv(
-                        self.programs.model,
-                        1,
-                        gl::FALSE,
-                        spatial.to_mat4().as_ptr(),
-                    );
-                    self.gl.DrawElements(
-                        gl::TRIANGLES,
-                        count,
-                        gl::UNSIGNED_SHORT,
-                        (offset * size_of::<u16>()) as *const c_void,
-                    );
+                if instance.layers() & layers == 0 {
+                    continue;
+                }
+                let mut visible = None;
+                instance.with_spatial(|spatial| {
+                    let matrix = spatial.to_mat4();
+                    let world_center = matrix.transform(center);
+                    if self.frustum.contains_sphere(&world_center, *radius) {
+                        let distance = world_center.distance(&self.camera_position);
+                        visible = Some((distance, matrix));
+                        drawn += 1;
+                    } else {
+                        culled += 1;
+                    }
                 });
+                if let Some((distance, matrix)) = visible {
+                    let level = model.0.level(distance);
+                    let material = instance
+                        .material()
+                        .unwrap_or_else(|| model_material.clone());
+                    // sampling the texture that is drawn into is undefined, e.g. a monitor seeing itself
+                    if self.target_texture != 0 && material.samples(self.target_texture) {
+                        continue;
+                    }
+                    if material.internal().blend == Blend::Transparent {
+                        if shadow_pass {
+                            continue;
+                        }
+                        transparent.push((distance, &model.0, material, level, matrix));
+                        continue;
+                    }
+                    let index = match groups[first..]
+                        .iter()
+                        .position(|group| group.material.ptr_eq(&material))
+                    {
+                        Some(index) => first + index,
+                        None => {
+                            let linked = self.linked(&mut variants, &material, shadow_pass);
+                            groups.push(DrawGroup::new(&model.0, linked, material));
+                            groups.len() - 1
+                        }
+                    };
+                    groups[index].levels[level].extend_from_slice(matrix.as_array());
+                }
             }
             model.0.instances.set(instances);
-            offset += count as usize;
         }
+        groups.sort_by_key(Gl::group_key);
+
+        let mut bound = Bound::default();
+        for group in &groups {
+            self.draw_group(group, &mut bound, time);
+        }
+
+        // after the opaque models, so it is only drawn where they left the cleared depth
+        if !shadow_pass && self.sky.sky.is_some() {
+            self.sky.draw(&self.gl, &self.camera, &self.camera_position);
+            bound = Bound::default();
+            unsafe {
+                self.gl.BindVertexArrayOES(self.vao_static.0);
+            }
+        }
+
+        if transparent.is_empty() {
+            return (drawn, culled);
+        }
+        transparent.sort_by(|(a, ..), (b, ..)| b.total_cmp(a));
+        // merge neighbours of the same model and material so they can still be batched
+        let mut merged: Vec<DrawGroup> = Vec::new();
+        for (_, model, material, level, matrix) in transparent {
+            let index = match merged.last() {
+                Some(last)
+                    if std::ptr::eq(last.model, model) && last.material.ptr_eq(&material) =>
+                {
+                    merged.len() - 1
+                }
+                _ => {
+                    let linked = self.linked(&mut variants, &material, false);
+                    merged.push(DrawGroup::new(model, linked, material));
+                    merged.len() - 1
+                }
+            };
+            merged[index].levels[level].extend_from_slice(matrix.as_array());
+        }
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            self.gl.DepthMask(gl::FALSE);
+        }
+        for group in &merged {
+            self.draw_group(group, &mut bound, time);
+        }
+        unsafe {
+            self.gl.DepthMask(gl::TRUE);
+            self.gl.Disable(gl::BLEND);
+        }
+        (drawn, culled)
     }
-    pub fn new_texture(&mut self, webp: WebP) -> Texture {
-        let mut handles = [0];
+    // Variant of the material's program for this pass, looked up once per material and view
+    fn linked(
+        &self,
+        variants: &mut Vec<(Material, Rc<Linked>)>,
+        material: &Material,
+        shadow_pass: bool,
+    ) -> Rc<Linked> {
+        if let Some((_, linked)) = variants.iter().find(|(other, _)| other.ptr_eq(material)) {
+            return linked.clone();
+        }
+        let mut defines = material.defines();
+        let linked = if shadow_pass {
+            // only alpha testing matters for the depth
+            defines.retain(|define| define == "TEXTURED" || define == "ALPHA_TEST");
+            self.shadow.program.variant(&self.gl, &defines)
+        } else {
+            if self.shadow.shadows.is_some() {
+                defines.push("SHADOWS".to_string());
+            }
+            if let Some(fog) = &self.fog {
+                defines.extend(fog.defines());
+            }
+            material.internal().program.variant(&self.gl, &defines)
+        };
+        variants.push((material.clone(), linked.clone()));
+        linked
+    }
+    fn draw_group(&mut self, group: &DrawGroup, bound: &mut Bound, time: f32) {
+        let material = group.material.internal();
+        let locations = &group.linked;
+        if bound.program != Some(locations.program.0) {
+            bound.program = Some(locations.program.0);
+            self.use_program(locations, time);
+        }
+        self.bind_textures(&material.textures, &mut bound.textures);
         unsafe {
-            self.gl.GenTextures(1, &mut handles as *mut _ as _);
-            self.gl.BindTexture(gl::TEXTURE_2D, handles[0]);
-            self.gl.TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGB as _,
-                webp.width as _,
-                webp.height as _,
-                0,
-                gl::RGB,
-                gl::UNSIGNED_BYTE,
-                webp.data.as_ptr() as _,
-            );
-            // only works because of the pixelstorei-command when Self is initialized
+            let [r, g, b, a] = material.tint;
+            self.gl.Uniform4f(locations.tint, r, g, b, a);
+            let [r, g, b] = material.emissive;
+            self.gl.Uniform3f(locations.emissive, r, g, b);
+            let [u, v] = material.uv_scroll;
+            self.gl.Uniform2f(locations.uv_scroll, u, v);
+            if let Blend::Cutout(cutoff) = material.blend {
+                self.gl.Uniform1f(locations.alpha_cutoff, cutoff);
+            }
+        }
+        for (name, value) in &material.uniforms {
+            locations.apply_uniform(&self.gl, name, value);
+        }
+        for (mesh, matrices) in group.model.meshes.iter().zip(&group.levels) {
+            if mesh.batch.get() == 0 {
+                continue;
+            }
+            for batch in matrices.chunks(mesh.batch.get() * 16) {
+                self.draw_batch(
+                    locations.models,
+                    batch,
+                    mesh.elements.len(),
+                    mesh.offset.get(),
+                );
+            }
+        }
+    }
+    // Programs first because switching them is the most expensive, then the first texture
+    fn group_key(group: &DrawGroup) -> (u32, u32) {
+        let texture = match group.material.internal().textures.first() {
+            Some(Some(texture)) => texture.0,
+            _ => 0,
+        };
+        (group.linked.program.0, texture)
+    }
+    fn use_program(&self, locations: &Linked, time: f32) {
+        unsafe {
+            self.gl.UseProgram(locations.program.0);
             self.gl
-                .TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+                .UniformMatrix4fv(locations.camera, 1, gl::FALSE, self.camera.as_ptr());
             self.gl
-                .TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+                .UniformMatrix4fv(locations.light, 1, gl::FALSE, self.light.as_ptr());
+            self.gl.Uniform1f(locations.time, time);
+        }
+        // bound every time, other textures may have replaced it on its unit since the shadow pass
+        if let Some(texture) = self.shadow.texture() {
+            if locations.shadow_map != -1 {
+                unsafe {
+                    self.gl
+                        .ActiveTexture(gl::TEXTURE0 + (SAMPLERS.len() - 1) as u32);
+                    self.gl.BindTexture(gl::TEXTURE_2D, texture);
+                    self.gl.ActiveTexture(gl::TEXTURE0);
+                }
+            }
+        }
+        if let Some(shadows) = self.shadow.shadows {
+            let uniforms = [
+                ("shadow_size", shadows.map_size as f32),
+                ("shadow_bias", shadows.bias),
+                ("shadow_strength", shadows.strength),
+            ];
+            for (name, value) in uniforms {
+                locations.apply_uniform(&self.gl, name, &UniformValue::Float(value));
+            }
+        }
+        if let Some(fog) = &self.fog {
+            let position = UniformValue::Vec3(*self.camera_position.as_array());
+            locations.apply_uniform(&self.gl, "camera_position", &position);
+            for (name, value) in &fog.uniforms(self.background) {
+                locations.apply_uniform(&self.gl, name, value);
+            }
         }
-        Texture(handles[0])
     }
-    pub fn drop_texture(&mut self, texture: &Texture) {
+    // Only rebinds texture units whose texture differs from the one in textures_bound.
+    // Every material unit is bound, so samplers beyond the textures of the material read white.
+    fn bind_textures(&self, textures: &[Option<Rc<Texture>>], textures_bound: &mut Vec<u32>) {
+        // the last sampler is the shadow map
+        let count = SAMPLERS.len() - 1;
+        if textures_bound.len() < count {
+            textures_bound.resize(count, 0);
+        }
+        for (slot, bound) in textures_bound.iter_mut().enumerate().take(count) {
+            let texture = match textures.get(slot) {
+                Some(Some(texture)) => texture.0,
+                _ => self.texture_white.0,
+            };
+            if *bound != texture {
+                *bound = texture;
+                unsafe {
+                    self.gl.ActiveTexture(gl::TEXTURE0 + slot as u32);
+                    self.gl.BindTexture(gl::TEXTURE_2D, texture);
+                }
+            }
+        }
+    }
+    fn draw_batch(&mut self, models: GLint, matrices: &[f32], count: usize, offset: usize) {
+        let instance_count = matrices.len() / 16;
         unsafe {
-            self.gl.DeleteTextures(1, &[texture.0] as *const _);
+            self.gl
+                .UniformMatrix4fv(models, instance_count as i32, gl::FALSE, matrices.as_ptr());
+            self.gl.DrawElements(
+                gl::TRIANGLES,
+                (count * instance_count) as i32,
+                gl::UNSIGNED_SHORT,
+                (offset * size_of::<u16>()) as *const c_void,
+            );
         }
+        self.stats.calls += 1;
+    }
+    pub fn stats(&self) -> &DrawStats {
+        &self.stats
+    }
+    pub fn new_texture(&mut self, image: &Image, sampler: Sampler) -> Texture {
+        Texture::new(
+            &self.gl,
+            image.width,
+            image.height,
+            image.alpha,
+            Some(&image.data),
+            sampler,
+        )
+    }
+    // None if the driver does not support the compression, the texture has to be decompressed then
+    pub fn new_texture_compressed(&mut self, ktx: &Ktx, sampler: Sampler) -> Option<Texture> {
+        match self.compressions.internal_format(ktx.compression) {
+            Some(format) => Some(Texture::new_compressed(&self.gl, ktx, format, sampler)),
+            None => {
+                #[cfg(debug_assertions)]
+                println!(
+                    "WARNING: {:?} textures are not supported by the driver, decompressing.",
+                    ktx.compression
+                );
+                None
+            }
+        }
+    }
+    // The image has to have the size and format the texture was created with
+    pub fn update_texture(&mut self, texture: &Texture, image: &Image, mipmaps: bool) {
+        texture.update(
+            &self.gl,
+            image.width,
+            image.height,
+            image.alpha,
+            &image.data,
+            mipmaps,
+        );
+    }
+    pub fn program_default(&self) -> ShaderProgram {
+        self.program_default.clone()
+    }
+    pub fn new_program(
+        &mut self,
+        vert_src: &Source,
+        frag_src: &Source,
+    ) -> Result<ShaderProgram, ShaderError> {
+        ShaderProgram::new(&self.gl, vert_src, frag_src)
+    }
+    pub fn drop_program(&mut self, program: &ShaderProgram) {
+        program.drop_with_gl(&self.gl);
+    }
+    #[cfg(debug_assertions)]
+    pub fn reload_program(&mut self, program: &ShaderProgram) {
+        program.reload(&self.gl);
+    }
+    // Programs of the post-processing, shadow, sky and sprite passes
+    #[cfg(debug_assertions)]
+    pub fn reload_internal_programs(&mut self) {
+        self.post.reload(&self.gl);
+        self.shadow.program.reload(&self.gl);
+        self.sky.reload(&self.gl);
+        self.sprites.reload(&self.gl);
+    }
+    pub fn new_render_target(&mut self, width: i32, height: i32, sampler: Sampler) -> RenderTarget {
+        RenderTarget::new(&self.gl, width, height, sampler, false, true)
+    }
+    pub fn drop_render_target(&mut self, target: &RenderTarget) {
+        target.drop_with_gl(&self.gl);
+    }
+    // Checkerboard texture for textures that could not be loaded
+    pub fn texture_missing(&self) -> Rc<Texture> {
+        self.texture_missing.clone()
+    }
+    pub fn drop_texture(&mut self, texture: &Texture) {
+        texture.drop_with_gl(&self.gl);
+    }
+    pub fn new_cube_map(&mut self, faces: &[Image], sampler: Sampler) -> CubeMap {
+        CubeMap::new(&self.gl, faces, sampler)
+    }
+    pub fn drop_cube_map(&mut self, cube_map: &CubeMap) {
+        cube_map.drop_with_gl(&self.gl);
     }
 }
 
 impl Drop for Gl {
     fn drop(&mut self) {
-        Programs::drop_with_gl(&mut self.programs, &self.gl);
+        self.program_default.drop_with_gl(&self.gl);
+        self.post.drop_with_gl(&self.gl);
+        self.shadow.drop_with_gl(&self.gl);
+        self.sky.drop_with_gl(&self.gl);
+        self.sprites.drop_with_gl(&self.gl);
+        self.texture_white.drop_with_gl(&self.gl);
+        self.texture_missing.drop_with_gl(&self.gl);
         Buffer::drop_with_gl(&mut self.arrays_static, &self.gl);
         Buffer::drop_with_gl(&mut self.elements_static, &self.gl);
         Vao::drop_with_gl(&mut self.vao_static, &self.gl);
     }
 }
 
+#[cfg(debug_assertions)]
 extern "system" fn debug_callback(
     _source: u32,
     _ty: u32,
@@ -236,141 +929,412 @@ extern "system" fn debug_callback(
     println!("GL DEBUG: {}", text);
 }
 
-struct Program(u32);
-struct Shader(u32);
-
-impl Program {
-    fn new(
-        gl: &Gles2,
-        vert_src: &'static str,
-        frag_src: &'static str,
-        attrib_locations: &[&'static str],
-    ) -> Program {
-        let handle = unsafe { gl.CreateProgram() };
-        let mut vert = Shader::new(gl, vert_src, gl::VERTEX_SHADER);
-        let mut frag = Shader::new(gl, frag_src, gl::FRAGMENT_SHADER);
-
-        for (i, name) in attrib_locations.iter().enumerate() {
-            let name_c = CString::new(*name).unwrap();
-            unsafe {
-                gl.BindAttribLocation(handle, i as GLuint, name_c.as_ptr());
-            }
-        }
+pub(super) struct Vao(pub(super) u32);
 
+impl Vao {
+    pub(super) fn new(gl: &Gles2) -> Vao {
+        let mut handles = [0];
         unsafe {
-            gl.AttachShader(handle, vert.0);
-            gl.AttachShader(handle, frag.0);
-            gl.LinkProgram(handle);
-            gl.DetachShader(handle, vert.0);
-            gl.DetachShader(handle, frag.0);
+            gl.GenVertexArraysOES(1, &mut handles as *mut [u32; 1] as *mut _);
         }
-
-        Shader::drop_with_gl(&mut frag, gl);
-        Shader::drop_with_gl(&mut vert, gl);
-
-        Program(handle)
+        Vao(handles[0])
     }
-    fn drop_with_gl(&mut self, gl: &Gles2) {
+    pub(super) fn drop_with_gl(&mut self, gl: &Gles2) {
+        let handles = [self.0];
         unsafe {
-            gl.DeleteProgram(self.0);
+            gl.DeleteVertexArraysOES(1, &handles as *const _);
         }
     }
 }
 
-impl Shader {
-    fn new(gl: &Gles2, src: &'static str, shader_type: u32) -> Shader {
-        let handle = unsafe { gl.CreateShader(shader_type) };
+pub(super) struct Buffer(pub(super) u32);
+
+impl Buffer {
+    pub(super) fn new(gl: &Gles2) -> Buffer {
+        let mut handles = [0];
         unsafe {
-            gl.ShaderSource(
-                handle,
-                1,
-                &[src.as_ptr()] as *const *const u8 as *const *const _,
-                &[src.len() as GLint] as *const _,
-            );
-            gl.CompileShader(handle);
+            gl.GenBuffers(1, &mut handles as *mut [u32; 1] as *mut _);
         }
-        Shader(handle)
+        Buffer(handles[0])
     }
-    fn drop_with_gl(&mut self, gl: &Gles2) {
+    pub(super) fn drop_with_gl(&mut self, gl: &Gles2) {
+        let handles = [self.0];
         unsafe {
-            gl.DeleteShader(self.0);
+            gl.DeleteBuffers(1, &handles as *const _);
         }
     }
 }
 
-struct Programs {
-    default: Program,
+pub struct Texture(pub(super) u32);
 
-    camera: GLint,
-    light: GLint,
-    model: GLint,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Linear,
 }
 
-impl Programs {
-    fn new(gl: &Gles2) -> Programs {
-        let default = Program::new(
-            gl,
-            include_str!("shaders/default.vert"),
-            include_str!("shaders/default.frag"),
-            &["pos_in", "tex_in"],
-        );
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+// How a texture is sampled, chosen when it is loaded. The default clamps, which works for every size,
+// tiling needs a power-of-two texture with Wrap::Repeat.
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    pub filter: Filter,
+    // only possible for power-of-two textures
+    pub mipmaps: bool,
+    pub wrap: Wrap,
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler {
+            filter: Filter::Nearest,
+            mipmaps: false,
+            wrap: Wrap::Clamp,
+        }
+    }
+}
+
+impl Sampler {
+    // GLES2 only supports clamping and no mipmaps for textures whose size is no power of two
+    fn restrict(mut self, width: u32, height: u32) -> Sampler {
+        if !width.is_power_of_two() || !height.is_power_of_two() {
+            #[cfg(debug_assertions)]
+            if self.mipmaps || self.wrap != Wrap::Clamp {
+                println!(
+                    "WARNING: Texture of size {}x{} is no power of two, clamping without mipmaps.",
+                    width, height
+                );
+            }
+            self.mipmaps = false;
+            self.wrap = Wrap::Clamp;
+        }
+        self
+    }
+    fn apply(&self, gl: &Gles2, target: u32) {
+        let (min_filter, mag_filter) = match (self.filter, self.mipmaps) {
+            (Filter::Nearest, false) => (gl::NEAREST, gl::NEAREST),
+            (Filter::Nearest, true) => (gl::NEAREST_MIPMAP_NEAREST, gl::NEAREST),
+            (Filter::Linear, false) => (gl::LINEAR, gl::LINEAR),
+            (Filter::Linear, true) => (gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR),
+        };
+        let wrap = match self.wrap {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::Clamp => gl::CLAMP_TO_EDGE,
+            Wrap::Mirror => gl::MIRRORED_REPEAT,
+        };
         unsafe {
-            gl.UseProgram(default.0); // probably only important for setting "tex"
+            gl.TexParameteri(target, gl::TEXTURE_MIN_FILTER, min_filter as _);
+            gl.TexParameteri(target, gl::TEXTURE_MAG_FILTER, mag_filter as _);
+            gl.TexParameteri(target, gl::TEXTURE_WRAP_S, wrap as _);
+            gl.TexParameteri(target, gl::TEXTURE_WRAP_T, wrap as _);
         }
-        let camera = unsafe { gl.GetUniformLocation(default.0, "camera\0".as_ptr() as *const _) };
-        let light = unsafe { gl.GetUniformLocation(default.0, "light\0".as_ptr() as *const _) };
-        let model = unsafe { gl.GetUniformLocation(default.0, "model\0".as_ptr() as *const _) };
+    }
+}
 
+impl Texture {
+    fn new(
+        gl: &Gles2,
+        width: u32,
+        height: u32,
+        alpha: bool,
+        data: Option<&[u8]>,
+        sampler: Sampler,
+    ) -> Texture {
+        let format = if alpha { gl::RGBA } else { gl::RGB };
+        let mut handles = [0];
         unsafe {
-            let tex = gl.GetUniformLocation(default.0, "tex\0".as_ptr() as *const _);
-            gl.Uniform1i(tex, 0);
+            gl.GenTextures(1, &mut handles as *mut _ as _);
+            gl.BindTexture(gl::TEXTURE_2D, handles[0]);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                format as _,
+                width as _,
+                height as _,
+                0,
+                format,
+                gl::UNSIGNED_BYTE,
+                // only works because of the pixelstorei-command when Gl is initialized
+                data.map_or(null(), |data| data.as_ptr()) as _,
+            );
         }
-        Programs {
-            default,
-            camera,
-            light,
-            model,
+        let sampler = sampler.restrict(width, height);
+        sampler.apply(gl, gl::TEXTURE_2D);
+        if sampler.mipmaps {
+            unsafe {
+                gl.GenerateMipmap(gl::TEXTURE_2D);
+            }
         }
+        Texture(handles[0])
     }
-    fn drop_with_gl(&mut self, gl: &Gles2) {
-        Program::drop_with_gl(&mut self.default, gl);
+    // Mipmaps cannot be generated for compressed textures, they are used if the file contains all of them
+    fn new_compressed(gl: &Gles2, ktx: &Ktx, format: u32, sampler: Sampler) -> Texture {
+        let mut sampler = sampler.restrict(ktx.width, ktx.height);
+        let levels_full = 32 - ktx.width.max(ktx.height).leading_zeros() as usize;
+        if sampler.mipmaps && ktx.levels.len() < levels_full {
+            #[cfg(debug_assertions)]
+            println!(
+                "WARNING: Compressed texture has {} of {} mipmap levels, disabling mipmaps.",
+                ktx.levels.len(),
+                levels_full
+            );
+            sampler.mipmaps = false;
+        }
+        let level_count = if sampler.mipmaps { levels_full } else { 1 };
+
+        let mut handles = [0];
+        unsafe {
+            gl.GenTextures(1, &mut handles as *mut _ as _);
+            gl.BindTexture(gl::TEXTURE_2D, handles[0]);
+            for (level, data) in ktx.levels.iter().take(level_count).enumerate() {
+                gl.CompressedTexImage2D(
+                    gl::TEXTURE_2D,
+                    level as _,
+                    format,
+                    (ktx.width >> level).max(1) as _,
+                    (ktx.height >> level).max(1) as _,
+                    0,
+                    data.len() as _,
+                    data.as_ptr() as _,
+                );
+            }
+        }
+        sampler.apply(gl, gl::TEXTURE_2D);
+        Texture(handles[0])
+    }
+    fn update(&self, gl: &Gles2, width: u32, height: u32, alpha: bool, data: &[u8], mipmaps: bool) {
+        let format = if alpha { gl::RGBA } else { gl::RGB };
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, self.0);
+            gl.TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                width as _,
+                height as _,
+                format,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as _,
+            );
+            if mipmaps {
+                gl.GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+    }
+    fn new_white(gl: &Gles2) -> Texture {
+        Texture::new(gl, 1, 1, false, Some(&[255, 255, 255]), Sampler::default())
+    }
+    fn new_missing(gl: &Gles2) -> Texture {
+        let image = checkerboard();
+        Texture::new(gl, 8, 8, false, Some(&image.data), Sampler::default())
+    }
+    fn drop_with_gl(&self, gl: &Gles2) {
+        unsafe {
+            gl.DeleteTextures(1, &[self.0] as *const _);
+        }
     }
 }
 
-struct Vao(u32);
+// Placeholder for images that could not be loaded
+pub(super) fn checkerboard() -> Image {
+    let data: Vec<u8> = (0..8 * 8)
+        .flat_map(|i| {
+            if (i % 8 + i / 8) % 2 == 0 {
+                [255, 0, 255]
+            } else {
+                [0, 0, 0]
+            }
+        })
+        .collect();
+    Image {
+        data,
+        width: 8,
+        height: 8,
+        alpha: false,
+    }
+}
 
-impl Vao {
-    fn new(gl: &Gles2) -> Vao {
+// Six square faces in the order +X, -X, +Y, -Y, +Z, -Z, sampled by direction
+pub struct CubeMap(pub(super) u32);
+
+impl CubeMap {
+    // The faces have to have the same size and format
+    fn new(gl: &Gles2, faces: &[Image], sampler: Sampler) -> CubeMap {
+        let size = faces[0].width;
+        // edges of neighbouring faces would be sampled otherwise
+        let mut sampler = sampler.restrict(size, size);
+        sampler.wrap = Wrap::Clamp;
         let mut handles = [0];
         unsafe {
-            gl.GenVertexArraysOES(1, &mut handles as *mut [u32; 1] as *mut _);
+            gl.GenTextures(1, &mut handles as *mut _ as _);
+            gl.BindTexture(gl::TEXTURE_CUBE_MAP, handles[0]);
+            for (index, face) in faces.iter().enumerate() {
+                let format = if face.alpha { gl::RGBA } else { gl::RGB };
+                gl.TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + index as u32,
+                    0,
+                    format as _,
+                    size as _,
+                    size as _,
+                    0,
+                    format,
+                    gl::UNSIGNED_BYTE,
+                    face.data.as_ptr() as _,
+                );
+            }
         }
-        Vao(handles[0])
+        sampler.apply(gl, gl::TEXTURE_CUBE_MAP);
+        if sampler.mipmaps {
+            unsafe {
+                gl.GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+            }
+        }
+        CubeMap(handles[0])
     }
-    fn drop_with_gl(&mut self, gl: &Gles2) {
-        let handles = [self.0];
+    fn drop_with_gl(&self, gl: &Gles2) {
         unsafe {
-            gl.DeleteVertexArraysOES(1, &handles as *const _);
+            gl.DeleteTextures(1, &[self.0] as *const _);
         }
     }
 }
 
-struct Buffer(u32);
+// Framebuffer with a texture as color attachment, which can be used in materials like any other texture,
+// e.g. for security-camera monitors, minimaps or mirrors.
+// Drawn every frame before the screen, as long as a camera is set.
+pub struct RenderTarget {
+    pub(super) framebuffer: u32,
+    // renderbuffer, targets that only get fullscreen passes drawn into them have none
+    depth: Option<u32>,
+    pub(super) texture: Rc<Texture>,
+    pub(super) size: (i32, i32),
+    // regenerated after every frame the target is drawn
+    mipmaps: bool,
 
-impl Buffer {
-    fn new(gl: &Gles2) -> Buffer {
-        let mut handles = [0];
+    camera: Cell<Option<(Mat4, Vec3)>>,
+    clear_color: Cell<[f32; 3]>,
+}
+
+impl RenderTarget {
+    pub(super) fn new(
+        gl: &Gles2,
+        width: i32,
+        height: i32,
+        sampler: Sampler,
+        alpha: bool,
+        depth: bool,
+    ) -> RenderTarget {
+        if width < 1 || height < 1 {
+            #[cfg(debug_assertions)]
+            println!(
+                "WARNING: Render target of size {}x{} is empty, using at least one pixel.",
+                width, height
+            );
+        }
+        let (width, height) = (width.max(1), height.max(1));
+        let sampler = sampler.restrict(width as u32, height as u32);
+        let mut texture = Texture::new(gl, width as u32, height as u32, alpha, None, sampler);
+        let mut framebuffer = 0;
+        let depth = if depth {
+            let mut depth = 0;
+            unsafe {
+                gl.GenRenderbuffers(1, &mut depth);
+                gl.BindRenderbuffer(gl::RENDERBUFFER, depth);
+                gl.RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT16, width, height);
+            }
+            Some(depth)
+        } else {
+            None
+        };
         unsafe {
-            gl.GenBuffers(1, &mut handles as *mut [u32; 1] as *mut _);
+            gl.GenFramebuffers(1, &mut framebuffer);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                texture.0,
+                0,
+            );
+            if let Some(depth) = depth {
+                gl.FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::RENDERBUFFER,
+                    depth,
+                );
+            }
+            // GLES2 does not guarantee that RGB is color-renderable, only RGBA
+            let _status = match gl.CheckFramebufferStatus(gl::FRAMEBUFFER) {
+                status if status != gl::FRAMEBUFFER_COMPLETE && !alpha => {
+                    texture.drop_with_gl(gl);
+                    texture = Texture::new(gl, width as u32, height as u32, true, None, sampler);
+                    gl.FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        gl::TEXTURE_2D,
+                        texture.0,
+                        0,
+                    );
+                    gl.CheckFramebufferStatus(gl::FRAMEBUFFER)
+                }
+                status => status,
+            };
+            #[cfg(debug_assertions)]
+            if _status != gl::FRAMEBUFFER_COMPLETE {
+                println!(
+                    "WARNING: Render target of size {}x{} is incomplete (status {:#x}).",
+                    width, height, _status
+                );
+            }
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        RenderTarget {
+            framebuffer,
+            depth,
+            texture: Rc::new(texture),
+            size: (width, height),
+            mipmaps: sampler.mipmaps,
+            camera: Cell::new(None),
+            clear_color: Cell::new([0.; 3]),
         }
-        Buffer(handles[0])
     }
-    fn drop_with_gl(&mut self, gl: &Gles2) {
-        let handles = [self.0];
+    pub fn texture(&self) -> Rc<Texture> {
+        self.texture.clone()
+    }
+    pub fn size(&self) -> (i32, i32) {
+        self.size
+    }
+    // Same as Visual::camera, but for this target
+    pub fn camera(&self, spatial: &Spatial, near: f32, far: f32, fov: f32) {
+        let aspect = (self.size.0 as f32) / (self.size.1 as f32);
+        // flipped vertically, so the first row of the texture is the top like in loaded images
+        let matrix = Mat4::new()
+            .scale(Vec3::new([1., -1., 1.]))
+            .perspective(near, far, fov, aspect)
+            .mul(spatial.to_mat4_rev());
+        self.camera.set(Some((matrix, spatial.position())));
+    }
+    // The target is not drawn anymore until a camera is set again
+    pub fn remove_camera(&self) {
+        self.camera.set(None);
+    }
+    pub fn clear_color(&self, r: f32, g: f32, b: f32) {
+        self.clear_color.set([r, g, b]);
+    }
+    pub(super) fn drop_with_gl(&self, gl: &Gles2) {
         unsafe {
-            gl.DeleteBuffers(1, &handles as *const _);
+            gl.DeleteFramebuffers(1, &[self.framebuffer] as *const _);
+            if let Some(depth) = self.depth {
+                gl.DeleteRenderbuffers(1, &[depth] as *const _);
+            }
         }
+        self.texture.drop_with_gl(gl);
     }
 }
-
-pub struct Texture(u32);
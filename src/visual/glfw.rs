@@ -74,6 +74,10 @@ impl Glfw {
             glfw::glfwPollEvents();
         }
     }
+    // Seconds since initialization
+    pub fn time(&self) -> f64 {
+        unsafe { glfw::glfwGetTime() }
+    }
     pub fn get_loadfn(&self) -> impl FnMut(&'static str) -> *const c_void {
         |proc| {
             let proc_c = CString::new(proc).expect("String conversion failed.");
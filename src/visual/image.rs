@@ -0,0 +1,55 @@
+use super::{ktx, png, qoi, webp};
+
+// Decoded image with 8 bits per channel, rows from top to bottom
+pub struct Image {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    // RGBA if true, RGB otherwise
+    pub alpha: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    WebP,
+    Png,
+    Qoi,
+    // ETC compressed, see Gl::new_texture_compressed for uploading without decompressing
+    Ktx,
+}
+
+impl Format {
+    // Detects the format by the magic bytes at the start of the file
+    pub fn sniff(src: &[u8]) -> Option<Format> {
+        if src.len() >= 12 && &src[0..4] == b"RIFF" && &src[8..12] == b"WEBP" {
+            Some(Format::WebP)
+        } else if src.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Format::Png)
+        } else if src.starts_with(b"qoif") {
+            Some(Format::Qoi)
+        } else if src.starts_with(b"\xabKTX 11\xbb") {
+            Some(Format::Ktx)
+        } else {
+            None
+        }
+    }
+}
+
+impl Image {
+    // Decodes to RGBA if the image has an alpha channel, to RGB otherwise
+    pub fn load(src: &[u8]) -> Option<Image> {
+        match Format::sniff(src)? {
+            Format::WebP => webp::decode(src),
+            Format::Png => png::decode(src),
+            Format::Qoi => qoi::decode(src),
+            Format::Ktx => ktx::decode(src),
+        }
+    }
+    pub fn channels(&self) -> usize {
+        if self.alpha {
+            4
+        } else {
+            3
+        }
+    }
+}
@@ -0,0 +1,306 @@
+// Decompression of zlib streams (RFC 1950 and 1951), as used by PNG
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order in which the code lengths of the code length alphabet are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn read(&mut self, count: u32) -> Option<u32> {
+        while self.count < count {
+            let byte = *self.data.get(self.position)?;
+            self.buffer |= (byte as u32) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+        Some(value)
+    }
+    // Drops the remaining bits of the current byte
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(bytes)
+    }
+}
+
+// Canonical Huffman code, given by the number of codes per length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+    fn decode(&self, bits: &mut Bits) -> Option<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in &self.counts[1..] {
+            code |= bits.read(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+// Returns None if the stream is malformed, the checksum is not verified
+pub(super) fn zlib(src: &[u8]) -> Option<Vec<u8>> {
+    let (method, flags) = (*src.first()?, *src.get(1)?);
+    if method & 0x0f != 8
+        || !u16::from_be_bytes([method, flags]).is_multiple_of(31)
+        || flags & 0x20 != 0
+    {
+        return None;
+    }
+    let mut bits = Bits {
+        data: &src[2..],
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let header = bits.bytes(4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if length != !complement {
+                    return None;
+                }
+                out.extend_from_slice(bits.bytes(length as usize)?);
+            }
+            1 => {
+                let mut lengths = [0; 288 + 30];
+                lengths[0..144].iter_mut().for_each(|length| *length = 8);
+                lengths[144..256].iter_mut().for_each(|length| *length = 9);
+                lengths[256..280].iter_mut().for_each(|length| *length = 7);
+                lengths[280..288].iter_mut().for_each(|length| *length = 8);
+                lengths[288..].iter_mut().for_each(|length| *length = 5);
+                let literals = Huffman::new(&lengths[..288]);
+                let distances = Huffman::new(&lengths[288..]);
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return None,
+        }
+        if last {
+            return Some(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Option<(Huffman, Huffman)> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[*index] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + bits.read(2)?),
+            17 => (0, 3 + bits.read(3)?),
+            18 => (0, 11 + bits.read(7)?),
+            _ => return None,
+        };
+        lengths.resize(lengths.len() + repeat as usize, length);
+    }
+    if lengths.len() != literal_count + distance_count {
+        return None;
+    }
+    Some((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Option<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Some(());
+        }
+        let index = symbol - 257;
+        let length =
+            *LENGTH_BASE.get(index)? as usize + bits.read(LENGTH_EXTRA[index] as u32)? as usize;
+        let index = distances.decode(bits)? as usize;
+        let distance =
+            *DISTANCE_BASE.get(index)? as usize + bits.read(DISTANCE_EXTRA[index] as u32)? as usize;
+        if distance > out.len() {
+            return None;
+        }
+        // byte by byte since the copied range may overlap with the bytes being written
+        let start = out.len() - distance;
+        for offset in 0..length {
+            let byte = out[start + offset];
+            out.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "abcabcabcabc hello hello" compressed by zlib into a single block with fixed codes
+    const FIXED: [u8; 21] = [
+        0x78, 0xda, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x85, 0x8c, 0xd4, 0x9c, 0x9c, 0x7c, 0x08,
+        0x09, 0x00, 0x70, 0x12, 0x09, 0x01,
+    ];
+    // the output of skewed_text(100) compressed by zlib into a single block with dynamic codes
+    const DYNAMIC: [u8; 68] = [
+        0x78, 0xda, 0x1d, 0x88, 0x41, 0x0a, 0xc0, 0x30, 0x0c, 0xc3, 0xde, 0x6a, 0xa8, 0x21, 0x83,
+        0x90, 0x40, 0xab, 0xfc, 0x7f, 0xeb, 0x7c, 0x90, 0x85, 0x3a, 0x89, 0xe0, 0x58, 0xd6, 0x48,
+        0x35, 0x34, 0x6e, 0xa6, 0xdb, 0x5d, 0xb0, 0xcb, 0x09, 0x08, 0xef, 0xbd, 0xbe, 0xb4, 0xf0,
+        0x01, 0x73, 0xd2, 0x2e, 0xdf, 0xe5, 0x05, 0x99, 0xd6, 0x35, 0x79, 0x3e, 0x3e, 0xf1, 0x47,
+        0xa3, 0xd0, 0xfd, 0x17, 0x52, 0xed, 0x29, 0xb2,
+    ];
+
+    // Letters with an uneven distribution, so zlib prefers dynamic codes
+    fn skewed_text(length: usize) -> Vec<u8> {
+        let letters = b"eeeeeeeettttaaooinshrdlu";
+        let mut state: u32 = 1;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+                letters[(state >> 16) as usize % letters.len()]
+            })
+            .collect()
+    }
+
+    // Stored blocks of the given contents, the checksum is left zero
+    fn stored(blocks: &[&[u8]]) -> Vec<u8> {
+        let mut src = vec![0x78, 0x01];
+        for (index, block) in blocks.iter().enumerate() {
+            src.push((index + 1 == blocks.len()) as u8);
+            src.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            src.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            src.extend_from_slice(block);
+        }
+        src.extend_from_slice(&[0; 4]);
+        src
+    }
+
+    #[test]
+    fn stored_blocks() {
+        let src = stored(&[b"hello ", b"", b"world"]);
+        assert_eq!(zlib(&src).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(zlib(&FIXED).unwrap(), b"abcabcabcabc hello hello");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(DYNAMIC[2] >> 1 & 3, 2);
+        assert_eq!(zlib(&DYNAMIC).unwrap(), skewed_text(100));
+    }
+
+    #[test]
+    fn truncated_streams() {
+        for src in [&FIXED[..], &DYNAMIC[..], &stored(&[b"hello"])[..]] {
+            // the checksum is not needed
+            for length in 0..src.len() - 4 {
+                assert_eq!(zlib(&src[..length]), None, "length {}", length);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_streams() {
+        // wrong compression method, check bits, preset dictionary
+        assert_eq!(zlib(&[0x79, 0xda, 0x03, 0x00]), None);
+        assert_eq!(zlib(&[0x78, 0xdb, 0x03, 0x00]), None);
+        assert_eq!(zlib(&[0x78, 0xbb, 0x03, 0x00]), None);
+        // length of a stored block does not match its complement
+        let mut src = stored(&[b"hello"]);
+        src[5] ^= 1;
+        assert_eq!(zlib(&src), None);
+        // reserved block type
+        assert_eq!(zlib(&[0x78, 0x01, 0x07, 0x00]), None);
+        // flipped bytes must not panic, whatever they decode to
+        for src in [&FIXED[..], &DYNAMIC[..]] {
+            for index in 2..src.len() {
+                for mask in [0x01, 0x10, 0xff] {
+                    let mut src = src.to_vec();
+                    src[index] ^= mask;
+                    let _ = zlib(&src);
+                }
+            }
+        }
+    }
+}
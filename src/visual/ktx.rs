@@ -0,0 +1,209 @@
+use super::etc;
+use super::image::Image;
+
+const IDENTIFIER: &[u8; 12] = b"\xabKTX 11\xbb\r\n\x1a\n";
+
+// Internal formats of the supported compressions
+pub(super) const ETC1_RGB8_OES: u32 = 0x8d64;
+pub(super) const COMPRESSED_RGB8_ETC2: u32 = 0x9274;
+pub(super) const COMPRESSED_RGBA8_ETC2_EAC: u32 = 0x9278;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Etc1,
+    Etc2Rgb,
+    Etc2Rgba,
+}
+
+impl Compression {
+    fn from_internal_format(format: u32) -> Option<Compression> {
+        match format {
+            ETC1_RGB8_OES => Some(Compression::Etc1),
+            COMPRESSED_RGB8_ETC2 => Some(Compression::Etc2Rgb),
+            COMPRESSED_RGBA8_ETC2_EAC => Some(Compression::Etc2Rgba),
+            _ => None,
+        }
+    }
+    // Bytes per block of 4x4 pixels
+    fn block_size(self) -> usize {
+        match self {
+            Compression::Etc1 | Compression::Etc2Rgb => 8,
+            Compression::Etc2Rgba => 16,
+        }
+    }
+}
+
+// Compressed 2D texture in a KTX 1 container, borrowing the level data from the file
+pub(super) struct Ktx<'a> {
+    pub(super) compression: Compression,
+    pub(super) width: u32,
+    pub(super) height: u32,
+    // the first level is the full image, followed by the mipmaps stored in the file
+    pub(super) levels: Vec<&'a [u8]>,
+}
+
+impl<'a> Ktx<'a> {
+    // Cube maps, arrays and 3D textures as well as uncompressed formats are not supported
+    pub(super) fn parse(src: &'a [u8]) -> Option<Ktx<'a>> {
+        let rest = src.strip_prefix(IDENTIFIER)?;
+        let header = rest.get(0..52)?;
+        let swap = match u32::from_le_bytes([header[0], header[1], header[2], header[3]]) {
+            0x04030201 => false,
+            0x01020304 => true,
+            _ => return None,
+        };
+        let read = |bytes: &[u8]| {
+            let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            if swap {
+                value.swap_bytes()
+            } else {
+                value
+            }
+        };
+        // fields following the endianness
+        let field = |index: usize| read(&header[4 + index * 4..]);
+        let (gl_type, internal_format) = (field(0), field(3));
+        let (width, height, depth) = (field(5), field(6), field(7));
+        let (array_elements, faces, level_count) = (field(8), field(9), field(10));
+        let key_value_bytes = field(11) as usize;
+        if gl_type != 0 || depth != 0 || array_elements != 0 || faces != 1 {
+            return None;
+        }
+        // a 32 bit size has no more than 32 levels, more would overflow the shifts below
+        if width == 0 || height == 0 || level_count > 32 {
+            return None;
+        }
+        let compression = Compression::from_internal_format(internal_format)?;
+
+        let mut rest = rest.get(52 + key_value_bytes..)?;
+        let mut levels = Vec::new();
+        for level in 0..level_count.max(1) {
+            let size = read(rest.get(0..4)?) as usize;
+            let data = rest.get(4..4 + size)?;
+            let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+            let blocks = level_width.div_ceil(4) as usize * level_height.div_ceil(4) as usize;
+            if size < blocks * compression.block_size() {
+                return None;
+            }
+            levels.push(data);
+            // image data is padded to 4 bytes
+            rest = rest.get((4 + size).next_multiple_of(4)..).unwrap_or(&[]);
+        }
+        Some(Ktx {
+            compression,
+            width,
+            height,
+            levels,
+        })
+    }
+    // Decompresses the first level
+    pub(super) fn decode(&self) -> Option<Image> {
+        match self.compression {
+            Compression::Etc1 | Compression::Etc2Rgb => {
+                etc::decode_rgb(self.levels[0], self.width, self.height)
+            }
+            Compression::Etc2Rgba => etc::decode_rgba(self.levels[0], self.width, self.height),
+        }
+    }
+}
+
+pub(super) fn decode(src: &[u8]) -> Option<Image> {
+    Ktx::parse(src)?.decode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // KTX file of a single level, big endian if swapped
+    fn ktx(internal_format: u32, width: u32, height: u32, data: &[u8], swapped: bool) -> Vec<u8> {
+        let word = |value: u32| {
+            if swapped {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let mut src = IDENTIFIER.to_vec();
+        let fields = [
+            0x04030201,
+            0,
+            1,
+            0,
+            internal_format,
+            0x1907,
+            width,
+            height,
+            0,
+            0,
+            1,
+            1,
+            0,
+        ];
+        for field in fields {
+            src.extend_from_slice(&word(field));
+        }
+        src.extend_from_slice(&word(data.len() as u32));
+        src.extend_from_slice(data);
+        src
+    }
+
+    #[test]
+    fn both_endiannesses() {
+        let data = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+        for swapped in [false, true] {
+            let src = ktx(ETC1_RGB8_OES, 4, 4, &data, swapped);
+            let ktx = Ktx::parse(&src).unwrap();
+            assert_eq!(ktx.compression, Compression::Etc1);
+            assert_eq!((ktx.width, ktx.height), (4, 4));
+            assert_eq!(ktx.levels, [&data[..]]);
+            assert_eq!(
+                ktx.decode().unwrap().data,
+                etc::decode_rgb(&data, 4, 4).unwrap().data
+            );
+        }
+    }
+
+    #[test]
+    fn rejected_headers() {
+        let data = [0; 16];
+        let src = ktx(COMPRESSED_RGBA8_ETC2_EAC, 4, 4, &data, false);
+        assert!(Ktx::parse(&src).is_some());
+
+        let mut bad_magic = src.clone();
+        bad_magic[1] = b'X';
+        assert!(Ktx::parse(&bad_magic).is_none());
+
+        let mut bad_endianness = src.clone();
+        bad_endianness[12..16].copy_from_slice(&0x04030200u32.to_le_bytes());
+        assert!(Ktx::parse(&bad_endianness).is_none());
+
+        // uncompressed formats
+        assert!(Ktx::parse(&ktx(0x8058, 4, 4, &data, false)).is_none());
+        assert!(Ktx::parse(&ktx(COMPRESSED_RGB8_ETC2, 0, 4, &data, false)).is_none());
+
+        // more levels than a 32 bit size can have, all of them present
+        let mut too_many_levels = ktx(COMPRESSED_RGB8_ETC2, 1, 1, &[0; 8], false);
+        too_many_levels[12 + 4 * 11..12 + 4 * 12].copy_from_slice(&33u32.to_le_bytes());
+        for _ in 1..33 {
+            too_many_levels.extend_from_slice(&8u32.to_le_bytes());
+            too_many_levels.extend_from_slice(&[0; 8]);
+        }
+        assert!(Ktx::parse(&too_many_levels).is_none());
+    }
+
+    #[test]
+    fn short_level_data() {
+        // 8x4 pixels need two blocks
+        let src = ktx(COMPRESSED_RGB8_ETC2, 8, 4, &[0; 8], false);
+        assert!(Ktx::parse(&src).is_none());
+        let src = ktx(COMPRESSED_RGB8_ETC2, 8, 4, &[0; 16], false);
+        for length in 0..src.len() {
+            assert!(Ktx::parse(&src[..length]).is_none(), "length {}", length);
+        }
+        // a second level is announced but missing
+        let mut src = src;
+        src[12 + 4 * 11..12 + 4 * 12].copy_from_slice(&2u32.to_le_bytes());
+        assert!(Ktx::parse(&src).is_none());
+    }
+}
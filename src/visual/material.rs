@@ -0,0 +1,122 @@
+use super::gl::Texture;
+use super::shader::{ShaderProgram, UniformError, UniformValue};
+use std::rc::Rc;
+
+// Materials are immutable and cheap to clone, the with_* methods return a modified copy.
+// Instances sharing the same material (i.e. clones of it) are batched together.
+#[derive(Clone)]
+pub struct Material(Rc<MaterialInternal>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    Opaque,
+    // fragments with an alpha below the cutoff are discarded
+    Cutout(f32),
+    // blended with what is behind, drawn after everything opaque from back to front
+    Transparent,
+}
+
+#[derive(Clone)]
+pub struct MaterialInternal {
+    pub(super) program: ShaderProgram,
+    // index is the sampler slot (texture unit), None is bound as a white texture
+    pub(super) textures: Vec<Option<Rc<Texture>>>,
+
+    pub(super) tint: [f32; 4],
+    pub(super) emissive: [f32; 3],
+    // texture coordinate offset per second
+    pub(super) uv_scroll: [f32; 2],
+    pub(super) blend: Blend,
+    // additional uniforms of the program, checked against it when set
+    pub(super) uniforms: Vec<(String, UniformValue)>,
+    // select a variant of the program, see Material::defines for the ones set by the engine
+    pub(super) defines: Vec<String>,
+}
+
+impl Material {
+    pub fn new(program: ShaderProgram) -> Material {
+        Material(Rc::new(MaterialInternal {
+            program,
+            textures: Vec::new(),
+            tint: [1.; 4],
+            emissive: [0.; 3],
+            uv_scroll: [0.; 2],
+            blend: Blend::Opaque,
+            uniforms: Vec::new(),
+            defines: Vec::new(),
+        }))
+    }
+    fn modify<F>(&self, f: F) -> Material
+    where
+        F: FnOnce(&mut MaterialInternal),
+    {
+        let mut internal = (*self.0).clone();
+        f(&mut internal);
+        Material(Rc::new(internal))
+    }
+    // Clears the uniforms set by with_uniform because they belong to the previous program
+    pub fn with_program(&self, program: ShaderProgram) -> Material {
+        self.modify(|internal| {
+            internal.program = program;
+            internal.uniforms.clear();
+        })
+    }
+    // Slots 0 to 3 are the samplers tex to tex3, further ones are not bound
+    pub fn with_texture(&self, slot: usize, texture: Option<Rc<Texture>>) -> Material {
+        self.modify(|internal| {
+            if internal.textures.len() <= slot {
+                internal.textures.resize(slot + 1, None);
+            }
+            internal.textures[slot] = texture;
+        })
+    }
+    pub fn with_tint(&self, r: f32, g: f32, b: f32, a: f32) -> Material {
+        self.modify(|internal| internal.tint = [r, g, b, a])
+    }
+    pub fn with_emissive(&self, r: f32, g: f32, b: f32) -> Material {
+        self.modify(|internal| internal.emissive = [r, g, b])
+    }
+    // Scrolled textures should repeat, see Sampler
+    pub fn with_uv_scroll(&self, u: f32, v: f32) -> Material {
+        self.modify(|internal| internal.uv_scroll = [u, v])
+    }
+    pub fn with_blend(&self, blend: Blend) -> Material {
+        self.modify(|internal| internal.blend = blend)
+    }
+    pub fn with_uniform(&self, name: &str, value: UniformValue) -> Result<Material, UniformError> {
+        self.0.program.check_uniform(name, &value)?;
+        Ok(self.modify(|internal| {
+            internal.uniforms.retain(|(other, _)| other != name);
+            internal.uniforms.push((name.to_string(), value));
+        }))
+    }
+    // Adds a #define to the shaders of this material, either "NAME" or "NAME VALUE"
+    pub fn with_define(&self, define: &str) -> Material {
+        self.modify(|internal| internal.defines.push(define.to_string()))
+    }
+    // Defines of the program variant used for this material, including the ones set by the engine:
+    // TEXTURED if a texture is bound to the first slot, ALPHA_TEST for cutout blending
+    pub(super) fn defines(&self) -> Vec<String> {
+        let mut defines = self.0.defines.clone();
+        if let Some(Some(_)) = self.0.textures.first() {
+            defines.push("TEXTURED".to_string());
+        }
+        if let Blend::Cutout(_) = self.0.blend {
+            defines.push("ALPHA_TEST".to_string());
+        }
+        defines
+    }
+    pub(super) fn internal(&self) -> &MaterialInternal {
+        &self.0
+    }
+    // Whether the texture with this handle is in one of the slots
+    pub(super) fn samples(&self, texture: u32) -> bool {
+        self.0
+            .textures
+            .iter()
+            .any(|slot| matches!(slot, Some(slot) if slot.0 == texture))
+    }
+    pub(super) fn ptr_eq(&self, other: &Material) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
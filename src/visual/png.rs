@@ -0,0 +1,427 @@
+use super::image::Image;
+use super::inflate;
+
+// Start and step of every pass of Adam7 interlacing: (x, y, dx, dy)
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Header {
+    width: u32,
+    height: u32,
+    depth: u8,
+    color: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1, // grayscale and palette
+        }
+    }
+}
+
+// Supports every color type, bit depth and interlacing, 16 bit channels are reduced to 8 bits.
+// Checksums and ancillary chunks except tRNS are ignored.
+pub fn decode(src: &[u8]) -> Option<Image> {
+    let mut rest = src.strip_prefix(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: Option<&[u8]> = None;
+    let mut compressed = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = &rest[4..8];
+        let data = rest.get(8..8 + length)?;
+        rest = rest.get(12 + length..)?;
+        match kind {
+            b"IHDR" if data.len() >= 13 => {
+                header = Some(Header {
+                    width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                    height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+                    depth: data[8],
+                    color: data[9],
+                    interlaced: data[12] == 1,
+                })
+            }
+            b"PLTE" => palette = data,
+            b"tRNS" => transparency = Some(data),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let header = header?;
+    let valid_depth = match header.color {
+        0 => [1, 2, 4, 8, 16].contains(&header.depth),
+        3 => [1, 2, 4, 8].contains(&header.depth),
+        2 | 4 | 6 => [8, 16].contains(&header.depth),
+        _ => false,
+    };
+    if !valid_depth || header.width == 0 || header.height == 0 {
+        return None;
+    }
+    if header.color == 3 && palette.is_empty() {
+        return None;
+    }
+
+    let filtered = inflate::zlib(&compressed)?;
+    let alpha = header.color == 4 || header.color == 6 || transparency.is_some();
+    let channels = if alpha { 4 } else { 3 };
+    let passes: &[(u32, u32, u32, u32)] = if header.interlaced {
+        &ADAM7
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    let bits = header.channels() * header.depth as usize;
+    // width, height and bytes per row without the filter type of every pass
+    let pass_sizes: Vec<(u32, u32, usize)> = passes
+        .iter()
+        .map(|(x0, y0, dx, dy)| {
+            let width = header.width.saturating_sub(*x0).div_ceil(*dx);
+            let height = header.height.saturating_sub(*y0).div_ceil(*dy);
+            (width, height, (width as usize * bits).div_ceil(8))
+        })
+        .collect();
+    // a corrupt header must not allocate more than the decompressed rows can fill
+    let required = pass_sizes
+        .iter()
+        .filter(|(width, _, _)| *width > 0)
+        .try_fold(0usize, |sum, (_, height, stride)| {
+            sum.checked_add((*height as usize).checked_mul(1 + stride)?)
+        })?;
+    if filtered.len() < required {
+        return None;
+    }
+    let size = (header.width as usize)
+        .checked_mul(header.height as usize)?
+        .checked_mul(channels)?;
+    let mut image = Image {
+        data: vec![0; size],
+        width: header.width,
+        height: header.height,
+        alpha,
+    };
+    let mut remaining = &filtered[..];
+    for ((x0, y0, dx, dy), (width, height, stride)) in passes.iter().zip(pass_sizes) {
+        if width == 0 || height == 0 {
+            continue;
+        }
+        // distance to the corresponding byte of the previous pixel, at least one byte
+        let distance = bits.div_ceil(8).max(1);
+        let mut previous = vec![0; stride];
+        for y in 0..height {
+            let filter = *remaining.first()?;
+            let mut row = remaining.get(1..1 + stride)?.to_vec();
+            remaining = &remaining[1 + stride..];
+            unfilter(filter, &mut row, &previous, distance)?;
+            for x in 0..width {
+                let pixel = pixel(&header, &row, x as usize, palette, transparency)?;
+                let target_x = (x0 + x * dx) as usize;
+                let target_y = (y0 + y * dy) as usize;
+                let target = (target_y * header.width as usize + target_x) * channels;
+                image.data[target..target + channels].copy_from_slice(&pixel[..channels]);
+            }
+            previous = row;
+        }
+    }
+    Some(image)
+}
+
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], distance: usize) -> Option<()> {
+    for i in 0..row.len() {
+        let left = if i >= distance { row[i - distance] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= distance {
+            previous[i - distance]
+        } else {
+            0
+        };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return None,
+        };
+        row[i] = row[i].wrapping_add(predicted);
+    }
+    Some(())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+// Raw value of the sample at the given index of an unfiltered row
+fn sample(row: &[u8], index: usize, depth: u8) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+        }
+    }
+}
+
+// Converts the pixel at x to RGBA
+fn pixel(
+    header: &Header,
+    row: &[u8],
+    x: usize,
+    palette: &[u8],
+    transparency: Option<&[u8]>,
+) -> Option<[u8; 4]> {
+    let channels = header.channels();
+    let depth = header.depth;
+    let raw: Vec<u16> = (0..channels)
+        .map(|channel| sample(row, x * channels + channel, depth))
+        .collect();
+    let scale = |value: u16| match depth {
+        16 => (value >> 8) as u8,
+        8 => value as u8,
+        _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+    };
+    // transparent color of grayscale and RGB images, compared before scaling
+    let keyed = |values: &[u16]| match transparency {
+        Some(key) if key.len() >= values.len() * 2 => values
+            .iter()
+            .enumerate()
+            .all(|(i, value)| u16::from_be_bytes([key[i * 2], key[i * 2 + 1]]) == *value),
+        _ => false,
+    };
+    Some(match header.color {
+        0 => {
+            let gray = scale(raw[0]);
+            let alpha = if keyed(&raw) { 0 } else { 255 };
+            [gray, gray, gray, alpha]
+        }
+        2 => {
+            let alpha = if keyed(&raw) { 0 } else { 255 };
+            [scale(raw[0]), scale(raw[1]), scale(raw[2]), alpha]
+        }
+        3 => {
+            let index = raw[0] as usize;
+            let color = palette.get(index * 3..index * 3 + 3)?;
+            let alpha = transparency
+                .and_then(|alphas| alphas.get(index).copied())
+                .unwrap_or(255);
+            [color[0], color[1], color[2], alpha]
+        }
+        4 => {
+            let gray = scale(raw[0]);
+            [gray, gray, gray, scale(raw[1])]
+        }
+        _ => [scale(raw[0]), scale(raw[1]), scale(raw[2]), scale(raw[3])],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Zlib stream of a single stored block, the checksum is left zero
+    fn stored(data: &[u8]) -> Vec<u8> {
+        let mut src = vec![0x78, 0x01, 0x01];
+        src.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        src.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        src.extend_from_slice(data);
+        src.extend_from_slice(&[0; 4]);
+        src
+    }
+
+    // PNG file of the given chunks after IHDR, with zero checksums
+    fn png(width: u32, height: u32, depth: u8, color: u8, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color, 0, 0, 0]);
+        let mut src = b"\x89PNG\r\n\x1a\n".to_vec();
+        let ihdr: (&[u8; 4], &[u8]) = (b"IHDR", &header);
+        let iend: (&[u8; 4], &[u8]) = (b"IEND", &[]);
+        for (kind, data) in [ihdr].iter().chain(chunks).chain(&[iend]) {
+            src.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            src.extend_from_slice(*kind);
+            src.extend_from_slice(data);
+            src.extend_from_slice(&[0; 4]);
+        }
+        src
+    }
+
+    // Filters a row the way an encoder would, with bpp bytes per pixel
+    fn filter(kind: u8, row: &[u8], previous: &[u8], bpp: usize) -> Vec<u8> {
+        let mut filtered = vec![kind];
+        for i in 0..row.len() {
+            let a = if i >= bpp { row[i - bpp] as i16 } else { 0 };
+            let b = previous[i] as i16;
+            let c = if i >= bpp {
+                previous[i - bpp] as i16
+            } else {
+                0
+            };
+            let predictor = match kind {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => (a + b) / 2,
+                _ => {
+                    let p = a + b - c;
+                    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                    if pa <= pb && pa <= pc {
+                        a
+                    } else if pb <= pc {
+                        b
+                    } else {
+                        c
+                    }
+                }
+            };
+            filtered.push(row[i].wrapping_sub(predictor as u8));
+        }
+        filtered
+    }
+
+    #[test]
+    fn every_filter_type() {
+        let rows: [[u8; 6]; 5] = [
+            [10, 20, 30, 200, 100, 50],
+            [15, 25, 35, 190, 110, 40],
+            [255, 0, 128, 1, 2, 3],
+            [90, 180, 45, 250, 5, 125],
+            [7, 77, 177, 177, 77, 7],
+        ];
+        let mut data = Vec::new();
+        let mut previous = [0; 6];
+        for (kind, row) in rows.iter().enumerate() {
+            data.extend(filter(kind as u8, row, &previous, 3));
+            previous = *row;
+        }
+        let src = png(2, 5, 8, 2, &[(b"IDAT", &stored(&data))]);
+        let image = decode(&src).unwrap();
+        assert_eq!((image.width, image.height, image.alpha), (2, 5, false));
+        assert_eq!(image.data, rows.concat());
+    }
+
+    #[test]
+    fn palette_with_transparency() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+        // 2 bits per index, the last pixel is padding of the byte
+        let data = [0, 0b00_01_10_11, 0b10_00_00_00];
+        let src = png(
+            5,
+            1,
+            2,
+            3,
+            &[
+                (b"PLTE", &palette),
+                (b"tRNS", &[128, 255]),
+                (b"IDAT", &stored(&data)),
+            ],
+        );
+        let image = decode(&src).unwrap();
+        assert!(image.alpha);
+        #[rustfmt::skip]
+        assert_eq!(image.data, [
+            255, 0, 0, 128,
+            0, 255, 0, 255,
+            0, 0, 255, 255,
+            9, 9, 9, 255,
+            0, 0, 255, 255,
+        ]);
+        // indices beyond the palette
+        let src = png(
+            1,
+            1,
+            8,
+            3,
+            &[(b"PLTE", &palette), (b"IDAT", &stored(&[0, 4]))],
+        );
+        assert!(decode(&src).is_none());
+    }
+
+    #[test]
+    fn sixteen_bits_are_reduced() {
+        let data = [0, 0x12, 0x34, 0xab, 0xcd, 0xff, 0x00, 0x80, 0x7f];
+        let src = png(1, 1, 16, 6, &[(b"IDAT", &stored(&data))]);
+        assert_eq!(decode(&src).unwrap().data, [0x12, 0xab, 0xff, 0x80]);
+    }
+
+    #[test]
+    fn grayscale_bit_depths() {
+        let src = png(4, 1, 1, 0, &[(b"IDAT", &stored(&[0, 0b1010_0000]))]);
+        #[rustfmt::skip]
+        assert_eq!(decode(&src).unwrap().data, [
+            255, 255, 255, 0, 0, 0, 255, 255, 255, 0, 0, 0,
+        ]);
+        let src = png(2, 1, 4, 0, &[(b"IDAT", &stored(&[0, 0x5f]))]);
+        assert_eq!(decode(&src).unwrap().data, [85, 85, 85, 255, 255, 255]);
+    }
+
+    #[test]
+    fn adam7_interlacing() {
+        // gray values y * 3 + x of a 3x3 image, split into the passes that are not empty
+        #[rustfmt::skip]
+        let data = [
+            0, 0,
+            0, 2,
+            0, 6, 8,
+            0, 1,
+            0, 7,
+            0, 3, 4, 5,
+        ];
+        let src = png(3, 3, 8, 0, &[(b"IDAT", &stored(&data))]);
+        let mut header = src[16..29].to_vec();
+        header[12] = 1;
+        let src = [&src[..16], &header, &src[29..]].concat();
+        let image = decode(&src).unwrap();
+        let gray: Vec<u8> = image.data.chunks(3).map(|pixel| pixel[0]).collect();
+        assert_eq!(gray, [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn truncated_and_corrupt_files() {
+        let data = [0, 1, 2, 3, 0, 4, 5, 6];
+        let src = png(1, 2, 8, 2, &[(b"IDAT", &stored(&data))]);
+        assert!(decode(&src).is_some());
+        // the image data ends with the IDAT chunk, the checksums and IEND are not needed
+        let end = src.len() - 12 - 4 - 4;
+        for length in 0..end {
+            assert!(decode(&src[..length]).is_none(), "length {}", length);
+        }
+        for index in 8..src.len() {
+            for mask in [0x01, 0x80, 0xff] {
+                let mut src = src.clone();
+                src[index] ^= mask;
+                let _ = decode(&src);
+            }
+        }
+        // unknown filter type and invalid bit depth
+        let src = png(1, 1, 8, 2, &[(b"IDAT", &stored(&[5, 1, 2, 3]))]);
+        assert!(decode(&src).is_none());
+        let src = png(1, 1, 4, 2, &[(b"IDAT", &stored(&[0, 1, 2, 3]))]);
+        assert!(decode(&src).is_none());
+    }
+}
@@ -0,0 +1,374 @@
+use std::mem::size_of;
+use std::ptr::null;
+use std::rc::Rc;
+
+use crate::visual::generated::gl;
+use crate::visual::generated::gl::Gles2;
+
+use super::gl::{Buffer, Filter, RenderTarget, Sampler, Texture, Vao, Wrap};
+use super::resolution::Resolution;
+use super::shader::{Linked, ShaderError, ShaderProgram, Source, UniformValue};
+
+// Effects applied to the drawn scene before it is shown, every one can be toggled separately.
+// Nothing is drawn offscreen if all of them are disabled.
+#[derive(Clone, Default)]
+pub struct PostProcessing {
+    pub bloom: Option<Bloom>,
+    pub color_grading: Option<ColorGrading>,
+    pub vignette: Option<Vignette>,
+    // anti-aliasing, applied last
+    pub fxaa: bool,
+}
+
+// Bright parts of the scene bleed into their surroundings
+#[derive(Clone, Copy, Debug)]
+pub struct Bloom {
+    // brightness from which on pixels bleed
+    pub threshold: f32,
+    pub intensity: f32,
+}
+
+// Colors are replaced by the ones in a lookup table
+#[derive(Clone)]
+pub struct ColorGrading {
+    // strip of size slices of size x size pixels, blue increases per slice, red to the right and green downwards.
+    // Should be loaded with linear filtering and clamping.
+    pub lut: Rc<Texture>,
+    pub size: u32,
+    // mix between the original (0) and the graded (1) colors
+    pub strength: f32,
+}
+
+// Darkens the corners of the screen
+#[derive(Clone, Copy, Debug)]
+pub struct Vignette {
+    pub intensity: f32,
+    // distance from the center where darkening starts, 1 is a corner
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom {
+            threshold: 0.8,
+            intensity: 0.6,
+        }
+    }
+}
+
+impl Default for Vignette {
+    fn default() -> Vignette {
+        Vignette {
+            intensity: 0.4,
+            radius: 0.5,
+        }
+    }
+}
+
+impl PostProcessing {
+    pub fn enabled(&self) -> bool {
+        self.bloom.is_some() || self.color_grading.is_some() || self.vignette.is_some() || self.fxaa
+    }
+}
+
+// Programs and offscreen targets of the post-processing passes, also upscaling the scene when it
+// is drawn at a lower resolution
+pub(super) struct PostChain {
+    vao: Vao,
+    // a single triangle covering the screen
+    arrays: Buffer,
+
+    bright: ShaderProgram,
+    blur: ShaderProgram,
+    composite: ShaderProgram,
+    fxaa: ShaderProgram,
+
+    // created when first needed, and again when the scaled size changes
+    size: (i32, i32),
+    render_size: (i32, i32),
+    scene: Option<RenderTarget>,
+    // scene with bloom, grading and vignette applied, input of FXAA
+    composited: Option<RenderTarget>,
+    // half resolution, blurred back and forth between them
+    bloom: Option<[RenderTarget; 2]>,
+}
+
+// Where a pass draws, the screen with the size of the default framebuffer
+enum Output<'a> {
+    Target(&'a RenderTarget),
+    Screen((i32, i32)),
+}
+
+impl PostChain {
+    pub(super) fn new(gl: &Gles2) -> Result<PostChain, ShaderError> {
+        let vert = Source::builtin("post.vert", include_str!("shaders/post.vert"));
+        let program = |name: &str, text: &'static str| {
+            ShaderProgram::new(gl, &vert, &Source::builtin(name, text))
+        };
+        let bright = program("bright.frag", include_str!("shaders/bright.frag"))?;
+        let blur = program("blur.frag", include_str!("shaders/blur.frag"))?;
+        let composite = program("composite.frag", include_str!("shaders/composite.frag"))?;
+        let fxaa = program("fxaa.frag", include_str!("shaders/fxaa.frag"))?;
+
+        let vao = Vao::new(gl);
+        let arrays = Buffer::new(gl);
+        let triangle: [f32; 6] = [-1., -1., 3., -1., -1., 3.];
+        unsafe {
+            gl.BindVertexArrayOES(vao.0);
+            gl.BindBuffer(gl::ARRAY_BUFFER, arrays.0);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (triangle.len() * size_of::<f32>()) as isize,
+                triangle.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            gl.EnableVertexAttribArray(0);
+            gl.VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, null());
+        }
+        Ok(PostChain {
+            vao,
+            arrays,
+            bright,
+            blur,
+            composite,
+            fxaa,
+            size: (0, 0),
+            render_size: (0, 0),
+            scene: None,
+            composited: None,
+            bloom: None,
+        })
+    }
+    // Binds and clears the target the scene is drawn into,
+    // false if no effect is enabled and the scene is drawn at full resolution
+    pub(super) fn begin(
+        &mut self,
+        gl: &Gles2,
+        settings: &PostProcessing,
+        resolution: &Resolution,
+        render_size: (i32, i32),
+    ) -> bool {
+        let size = resolution.size(render_size);
+        if !settings.enabled() && size == render_size {
+            return false;
+        }
+        if self.size != size {
+            self.drop_targets(gl);
+            self.size = size;
+        }
+        self.render_size = render_size;
+        let scene = self
+            .scene
+            .get_or_insert_with(|| PostChain::target(gl, size, true));
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, scene.framebuffer);
+            gl.Viewport(0, 0, size.0, size.1);
+            gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        true
+    }
+    // Applies the effects to the scene drawn after begin and draws the result to the screen
+    pub(super) fn finish(
+        &mut self,
+        gl: &Gles2,
+        settings: &PostProcessing,
+        resolution: &Resolution,
+    ) {
+        let (size, render_size) = (self.size, self.render_size);
+        let scene = match &self.scene {
+            Some(scene) => scene.texture.0,
+            None => return,
+        };
+        unsafe {
+            gl.Disable(gl::DEPTH_TEST);
+            gl.BindVertexArrayOES(self.vao.0);
+        }
+        let texel = |size: (i32, i32)| UniformValue::Vec2([1. / size.0 as f32, 1. / size.1 as f32]);
+
+        let mut composite_defines = Vec::new();
+        let mut composite_uniforms = Vec::new();
+        let mut composite_textures = vec![scene];
+        if let Some(bloom) = &settings.bloom {
+            let half = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+            let targets = self.bloom.get_or_insert_with(|| {
+                [
+                    PostChain::target(gl, half, false),
+                    PostChain::target(gl, half, false),
+                ]
+            });
+            let bright = self.bright.linked();
+            PostChain::pass(
+                gl,
+                &bright,
+                Output::Target(&targets[0]),
+                &[scene],
+                &[
+                    ("texel", texel(size)),
+                    ("threshold", UniformValue::Float(bloom.threshold)),
+                ],
+            );
+            let blur = self.blur.linked();
+            let [x, y] = [1. / half.0 as f32, 1. / half.1 as f32];
+            PostChain::pass(
+                gl,
+                &blur,
+                Output::Target(&targets[1]),
+                &[targets[0].texture.0],
+                &[("direction", UniformValue::Vec2([x, 0.]))],
+            );
+            PostChain::pass(
+                gl,
+                &blur,
+                Output::Target(&targets[0]),
+                &[targets[1].texture.0],
+                &[("direction", UniformValue::Vec2([0., y]))],
+            );
+            composite_defines.push("BLOOM".to_string());
+            composite_uniforms.push(("bloom_intensity", UniformValue::Float(bloom.intensity)));
+            composite_textures.push(targets[0].texture.0);
+        }
+        if let Some(grading) = &settings.color_grading {
+            composite_defines.push("COLOR_GRADING".to_string());
+            composite_uniforms.push(("lut_size", UniformValue::Float(grading.size as f32)));
+            composite_uniforms.push(("lut_strength", UniformValue::Float(grading.strength)));
+            composite_textures.resize(2, 0);
+            composite_textures.push(grading.lut.0);
+        }
+        if let Some(vignette) = &settings.vignette {
+            composite_defines.push("VIGNETTE".to_string());
+            composite_uniforms.push((
+                "vignette",
+                UniformValue::Vec2([vignette.intensity, vignette.radius]),
+            ));
+        }
+
+        // without FXAA the composite pass draws to the screen, and is skipped if FXAA would only copy
+        let composite = self.composite.variant(gl, &composite_defines);
+        let upscale = if size == render_size {
+            Filter::Linear
+        } else {
+            resolution.filter
+        };
+        if !settings.fxaa {
+            PostChain::filter(gl, scene, upscale);
+            PostChain::pass(
+                gl,
+                &composite,
+                Output::Screen(render_size),
+                &composite_textures,
+                &composite_uniforms,
+            );
+            PostChain::filter(gl, scene, Filter::Linear);
+        } else {
+            let input = if composite_defines.is_empty() {
+                scene
+            } else {
+                let composited = self
+                    .composited
+                    .get_or_insert_with(|| PostChain::target(gl, size, false));
+                PostChain::pass(
+                    gl,
+                    &composite,
+                    Output::Target(composited),
+                    &composite_textures,
+                    &composite_uniforms,
+                );
+                composited.texture.0
+            };
+            let fxaa = self.fxaa.linked();
+            PostChain::filter(gl, input, upscale);
+            PostChain::pass(
+                gl,
+                &fxaa,
+                Output::Screen(render_size),
+                &[input],
+                &[("texel", texel(size))],
+            );
+            PostChain::filter(gl, input, Filter::Linear);
+        }
+        unsafe {
+            gl.Enable(gl::DEPTH_TEST);
+        }
+    }
+    // Draws the fullscreen triangle into the whole output
+    fn pass(
+        gl: &Gles2,
+        linked: &Linked,
+        output: Output,
+        textures: &[u32],
+        uniforms: &[(&str, UniformValue)],
+    ) {
+        unsafe {
+            let size = match output {
+                Output::Target(target) => {
+                    gl.BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+                    target.size
+                }
+                Output::Screen(render_size) => {
+                    gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                    render_size
+                }
+            };
+            gl.Viewport(0, 0, size.0, size.1);
+            gl.UseProgram(linked.program.0);
+            for (unit, texture) in textures.iter().enumerate() {
+                gl.ActiveTexture(gl::TEXTURE0 + unit as u32);
+                gl.BindTexture(gl::TEXTURE_2D, *texture);
+            }
+        }
+        for (name, value) in uniforms {
+            linked.apply_uniform(gl, name, value);
+        }
+        unsafe {
+            gl.DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+    }
+    // The targets are sampled linearly by all passes except the one drawing to the screen
+    fn filter(gl: &Gles2, texture: u32, filter: Filter) {
+        let filter = match filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        } as i32;
+        unsafe {
+            gl.ActiveTexture(gl::TEXTURE0);
+            gl.BindTexture(gl::TEXTURE_2D, texture);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+        }
+    }
+    fn target(gl: &Gles2, size: (i32, i32), depth: bool) -> RenderTarget {
+        let sampler = Sampler {
+            filter: Filter::Linear,
+            mipmaps: false,
+            wrap: Wrap::Clamp,
+        };
+        RenderTarget::new(gl, size.0, size.1, sampler, false, depth)
+    }
+    fn drop_targets(&mut self, gl: &Gles2) {
+        if let Some(scene) = self.scene.take() {
+            scene.drop_with_gl(gl);
+        }
+        if let Some(composited) = self.composited.take() {
+            composited.drop_with_gl(gl);
+        }
+        if let Some(bloom) = self.bloom.take() {
+            bloom.iter().for_each(|target| target.drop_with_gl(gl));
+        }
+    }
+    #[cfg(debug_assertions)]
+    pub(super) fn reload(&self, gl: &Gles2) {
+        self.bright.reload(gl);
+        self.blur.reload(gl);
+        self.composite.reload(gl);
+        self.fxaa.reload(gl);
+    }
+    pub(super) fn drop_with_gl(&mut self, gl: &Gles2) {
+        self.drop_targets(gl);
+        self.bright.drop_with_gl(gl);
+        self.blur.drop_with_gl(gl);
+        self.composite.drop_with_gl(gl);
+        self.fxaa.drop_with_gl(gl);
+        Buffer::drop_with_gl(&mut self.arrays, gl);
+        Vao::drop_with_gl(&mut self.vao, gl);
+    }
+}
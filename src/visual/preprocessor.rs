@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::shader::{ShaderError, Source};
+
+// Chunks which can be included by every shader, looked up after the directory of the including file
+const CHUNKS: [(&str, &str); 3] = [
+    ("fog.glsl", include_str!("shaders/fog.glsl")),
+    ("instancing.glsl", include_str!("shaders/instancing.glsl")),
+    ("shadow.glsl", include_str!("shaders/shadow.glsl")),
+];
+
+// Shader source after resolving includes and injecting defines
+pub(super) struct Expanded {
+    pub(super) name: String,
+    pub(super) text: String,
+    // file name and line number of every line of the text
+    origins: Vec<(Rc<str>, usize)>,
+    // included files that exist on disk, watched for hot-reloading
+    pub(super) dependencies: Vec<PathBuf>,
+}
+
+impl Expanded {
+    // Maps a line of the text (starting at 1) to the file and line it originates from
+    pub(super) fn locate(&self, line: usize) -> (&str, usize) {
+        match self.origins.get(line.wrapping_sub(1)) {
+            Some((name, line)) => (name, *line),
+            None => (&self.name, line),
+        }
+    }
+}
+
+// Resolves #include "name" directives (every file is included at most once) and
+// inserts a #define for every entry of defines ("NAME" or "NAME VALUE") after #version,
+// which may only be preceded by blank lines and comments
+pub(super) fn preprocess(source: &Source, defines: &[String]) -> Result<Expanded, ShaderError> {
+    let mut expanded = Expanded {
+        name: source.name.clone(),
+        text: String::new(),
+        origins: Vec::new(),
+        dependencies: Vec::new(),
+    };
+    let mut included = vec![source.name.clone()];
+
+    let lines: Vec<&str> = source.text.lines().collect();
+    let name: Rc<str> = Rc::from(source.name.as_str());
+    let first = lines.iter().position(|line| {
+        let line = line.trim_start();
+        !line.is_empty() && !line.starts_with("//")
+    });
+    // the defines are reported as the line they are inserted after
+    let version = match first {
+        Some(first) if lines[first].trim_start().starts_with("#version") => first + 1,
+        _ => 0,
+    };
+    for (number, line) in lines[..version].iter().enumerate() {
+        push_line(&mut expanded, line, &name, number + 1);
+    }
+    for define in defines {
+        let define = format!("#define {}", define);
+        push_line(&mut expanded, &define, &name, version.max(1));
+    }
+    let rest = lines.into_iter().enumerate().skip(version);
+    expand(&mut expanded, &mut included, source, rest)?;
+    Ok(expanded)
+}
+
+fn push_line(expanded: &mut Expanded, line: &str, name: &Rc<str>, number: usize) {
+    expanded.text.push_str(line);
+    expanded.text.push('\n');
+    expanded.origins.push((name.clone(), number));
+}
+
+fn expand<'a, I>(
+    expanded: &mut Expanded,
+    included: &mut Vec<String>,
+    source: &Source,
+    lines: I,
+) -> Result<(), ShaderError>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let name: Rc<str> = Rc::from(source.name.as_str());
+    for (number, line) in lines {
+        let directive = line.trim_start();
+        if !directive.starts_with("#include") {
+            push_line(expanded, line, &name, number + 1);
+            continue;
+        }
+        let error = |log: &str| ShaderError::Preprocess {
+            name: source.name.clone(),
+            log: format!("{}:{}: {}", source.name, number + 1, log),
+        };
+        let include = directive["#include".len()..]
+            .trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| error("expected #include \"file\""))?;
+        let chunk = resolve(source, include)
+            .ok_or_else(|| error(&format!("could not find \"{}\"", include)))?;
+        if included.contains(&chunk.name) {
+            continue;
+        }
+        included.push(chunk.name.clone());
+        if let Some(path) = &chunk.path {
+            expanded.dependencies.push(path.clone());
+        }
+        expand(expanded, included, &chunk, chunk.text.lines().enumerate())?;
+    }
+    Ok(())
+}
+
+fn resolve(source: &Source, include: &str) -> Option<Source> {
+    if let Some(dir) = source.path.as_ref().and_then(|path| path.parent()) {
+        if let Ok(chunk) = Source::file(dir.join(include)) {
+            return Some(chunk);
+        }
+    }
+    CHUNKS
+        .iter()
+        .find(|(name, _)| *name == include)
+        .map(|(name, text)| Source::builtin(name, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defines_follow_version_after_comments() {
+        let source = Source::new("test.frag", "// comment\n\n#version 100\nvoid main() {}");
+        let expanded = preprocess(&source, &["FOG".to_string()]).unwrap();
+        let lines: Vec<&str> = expanded.text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "// comment",
+                "",
+                "#version 100",
+                "#define FOG",
+                "void main() {}"
+            ]
+        );
+        assert_eq!(expanded.locate(3), ("test.frag", 3));
+        assert_eq!(expanded.locate(4), ("test.frag", 3));
+        assert_eq!(expanded.locate(5), ("test.frag", 4));
+    }
+
+    #[test]
+    fn defines_without_version_come_first() {
+        let source = Source::new("test.frag", "void main() {}");
+        let expanded = preprocess(&source, &["FOG".to_string()]).unwrap();
+        assert_eq!(expanded.text, "#define FOG\nvoid main() {}\n");
+        assert_eq!(expanded.locate(1), ("test.frag", 1));
+        assert_eq!(expanded.locate(2), ("test.frag", 1));
+    }
+
+    #[test]
+    fn chunks_are_included_once() {
+        let source = Source::new(
+            "test.vert",
+            "#include \"instancing.glsl\"\n#include \"instancing.glsl\"\nvoid main() {}",
+        );
+        let expanded = preprocess(&source, &[]).unwrap();
+        assert_eq!(expanded.text.matches("mat4 model_matrix()").count(), 1);
+        // debug builds read the chunk from the source tree
+        let (name, line) = expanded.locate(1);
+        assert!(name.ends_with("instancing.glsl"));
+        assert_eq!(line, 1);
+        let last = expanded.text.lines().count();
+        assert_eq!(expanded.locate(last), ("test.vert", 3));
+    }
+
+    #[test]
+    fn unknown_includes_are_errors() {
+        let source = Source::new("test.vert", "#include \"missing.glsl\"");
+        match preprocess(&source, &[]) {
+            Err(ShaderError::Preprocess { log, .. }) => assert!(log.starts_with("test.vert:1:")),
+            _ => panic!("expected a preprocess error"),
+        }
+    }
+}
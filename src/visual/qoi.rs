@@ -0,0 +1,158 @@
+use super::image::Image;
+
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const MASK: u8 = 0xc0;
+
+// Decodes the "Quite OK Image" format, see https://qoiformat.org/qoi-specification.pdf
+pub fn decode(src: &[u8]) -> Option<Image> {
+    let header = src.get(0..14)?;
+    if &header[0..4] != b"qoif" {
+        return None;
+    }
+    let width = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let height = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    let alpha = match header[12] {
+        3 => false,
+        4 => true,
+        _ => return None,
+    };
+    let pixels = (width as usize).checked_mul(height as usize)?;
+    // a single byte covers at most 62 pixels, reject files that are obviously too short early
+    if pixels == 0 || pixels / 62 > src.len() {
+        return None;
+    }
+
+    let channels = if alpha { 4 } else { 3 };
+    let mut data = Vec::with_capacity(pixels * channels);
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0, 0, 0, 255];
+    let mut position = 14;
+    let mut run = 0;
+    for _ in 0..pixels {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let op = *src.get(position)?;
+            position += 1;
+            match op {
+                OP_RGB => {
+                    pixel[0..3].copy_from_slice(src.get(position..position + 3)?);
+                    position += 3;
+                }
+                OP_RGBA => {
+                    pixel.copy_from_slice(src.get(position..position + 4)?);
+                    position += 4;
+                }
+                _ => match op & MASK {
+                    OP_INDEX => pixel = index[op as usize],
+                    OP_DIFF => {
+                        pixel[0] = pixel[0].wrapping_add((op >> 4) & 0x03).wrapping_sub(2);
+                        pixel[1] = pixel[1].wrapping_add((op >> 2) & 0x03).wrapping_sub(2);
+                        pixel[2] = pixel[2].wrapping_add(op & 0x03).wrapping_sub(2);
+                    }
+                    OP_LUMA => {
+                        let next = *src.get(position)?;
+                        position += 1;
+                        let green = (op & 0x3f).wrapping_sub(32);
+                        pixel[0] = pixel[0]
+                            .wrapping_add(green)
+                            .wrapping_add(next >> 4)
+                            .wrapping_sub(8);
+                        pixel[1] = pixel[1].wrapping_add(green);
+                        pixel[2] = pixel[2]
+                            .wrapping_add(green)
+                            .wrapping_add(next & 0x0f)
+                            .wrapping_sub(8);
+                    }
+                    _ => run = op & 0x3f, // OP_RUN, the current pixel is the first of the run
+                },
+            }
+            let hash = pixel[0] as usize * 3
+                + pixel[1] as usize * 5
+                + pixel[2] as usize * 7
+                + pixel[3] as usize * 11;
+            index[hash % 64] = pixel;
+        }
+        data.extend_from_slice(&pixel[..channels]);
+    }
+
+    Some(Image {
+        data,
+        width,
+        height,
+        alpha,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const OPS: [u8; 15] = [
+        OP_RGB, 10, 20, 30,
+        OP_DIFF | 3 << 4 | 1 << 2 | 2, // +1, -1, 0
+        OP_LUMA | (5 + 32), (-2i8 + 8) as u8 * 16 + (3 + 8), // green +5, red +3, blue +8
+        0xc0 | 1, // run of 2
+        OP_INDEX | 9, // the first pixel
+        OP_RGBA, 1, 2, 3, 4,
+        OP_INDEX | 7, // the second pixel
+    ];
+
+    fn qoi(width: u32, height: u32, channels: u8, ops: &[u8]) -> Vec<u8> {
+        let mut src = b"qoif".to_vec();
+        src.extend_from_slice(&width.to_be_bytes());
+        src.extend_from_slice(&height.to_be_bytes());
+        src.extend_from_slice(&[channels, 0]);
+        src.extend_from_slice(ops);
+        src.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        src
+    }
+
+    #[test]
+    fn every_op() {
+        let image = decode(&qoi(4, 2, 4, &OPS)).unwrap();
+        assert_eq!((image.width, image.height, image.alpha), (4, 2, true));
+        #[rustfmt::skip]
+        assert_eq!(image.data, [
+            10, 20, 30, 255,
+            11, 19, 30, 255,
+            14, 24, 38, 255,
+            14, 24, 38, 255,
+            14, 24, 38, 255,
+            10, 20, 30, 255,
+            1, 2, 3, 4,
+            11, 19, 30, 255,
+        ]);
+    }
+
+    #[test]
+    fn rgb_drops_alpha() {
+        let image = decode(&qoi(2, 1, 3, &[OP_RGB, 1, 2, 3, 0xc0])).unwrap();
+        assert!(!image.alpha);
+        assert_eq!(image.data, [1, 2, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn truncated_and_corrupt_files() {
+        let src = qoi(4, 2, 4, &OPS);
+        for length in 0..14 + OPS.len() {
+            assert!(decode(&src[..length]).is_none(), "length {}", length);
+        }
+        for index in 0..src.len() {
+            for mask in [0x01, 0x40, 0xff] {
+                let mut src = src.clone();
+                src[index] ^= mask;
+                let _ = decode(&src);
+            }
+        }
+        assert!(decode(&qoi(0, 2, 4, &OPS)).is_none());
+        assert!(decode(&qoi(4, 2, 5, &OPS)).is_none());
+        // far more pixels than the file could describe
+        assert!(decode(&qoi(1 << 16, 1 << 16, 4, &OPS)).is_none());
+    }
+}
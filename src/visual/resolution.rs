@@ -0,0 +1,144 @@
+use super::gl::Filter;
+
+// Steps the dynamic scale changes by, so the offscreen targets are not recreated every frame
+const STEP: f32 = 0.05;
+// Seconds between changes of the dynamic scale, the frame time needs a moment to settle
+const COOLDOWN: f64 = 0.5;
+
+// Resolution the scene is drawn at, below the render size it is upscaled to the window
+#[derive(Clone, Copy, Debug)]
+pub struct Resolution {
+    // fraction of the render size per axis, 1 draws at full resolution
+    pub scale: f32,
+    // filter of the upscaling, Nearest keeps pixels sharp
+    pub filter: Filter,
+    // adapts scale to the frame time if set
+    pub dynamic: Option<DynamicResolution>,
+}
+
+// Lowers the scale while frames take longer than the target and raises it again when they are faster.
+// Frame times do not drop below the refresh interval with vsync, so the target should be above it,
+// for example 1/50 seconds on a 60 Hz display.
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicResolution {
+    // seconds
+    pub target_frame_time: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+}
+
+impl Default for Resolution {
+    fn default() -> Resolution {
+        Resolution {
+            scale: 1.,
+            filter: Filter::Linear,
+            dynamic: None,
+        }
+    }
+}
+
+impl Default for DynamicResolution {
+    fn default() -> DynamicResolution {
+        DynamicResolution {
+            target_frame_time: 1. / 50.,
+            min_scale: 0.5,
+            max_scale: 1.,
+        }
+    }
+}
+
+impl Resolution {
+    // Size the scene is drawn at
+    pub fn size(&self, render_size: (i32, i32)) -> (i32, i32) {
+        let scale = self.scale.clamp(0., 1.);
+        (
+            ((render_size.0 as f32 * scale).round() as i32).max(1),
+            ((render_size.1 as f32 * scale).round() as i32).max(1),
+        )
+    }
+}
+
+// Measures the time between frames for the dynamic resolution
+#[derive(Default)]
+pub(super) struct FrameTimer {
+    last: Option<f64>,
+    // smoothed, so single slow frames do not change the scale
+    average: Option<f32>,
+    changed: f64,
+}
+
+impl FrameTimer {
+    // Called once per frame with the current time in seconds
+    pub(super) fn adapt(&mut self, now: f64, resolution: &mut Resolution) {
+        let last = self.last.replace(now);
+        let dynamic = match resolution.dynamic {
+            Some(dynamic) => dynamic,
+            None => {
+                self.average = None;
+                return;
+            }
+        };
+        let frame_time = match last {
+            Some(last) => (now - last) as f32,
+            None => return,
+        };
+        let average = match self.average {
+            Some(average) => average * 0.9 + frame_time * 0.1,
+            None => frame_time,
+        };
+        self.average = Some(average);
+        if now - self.changed < COOLDOWN {
+            return;
+        }
+
+        let scale = if average > dynamic.target_frame_time * 1.1 {
+            resolution.scale - STEP
+        } else if average < dynamic.target_frame_time * 0.9 {
+            resolution.scale + STEP
+        } else {
+            return;
+        };
+        // swapped or NaN limits would make clamp panic
+        let min = dynamic.min_scale.min(dynamic.max_scale).max(STEP);
+        let max = dynamic.max_scale.max(dynamic.min_scale).min(1.).max(min);
+        let scale = scale.max(min).min(max);
+        if scale != resolution.scale {
+            resolution.scale = scale;
+            self.changed = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scale after a slow and a fast frame following the first one
+    fn adapted(min_scale: f32, max_scale: f32) -> [f32; 2] {
+        let mut resolution = Resolution {
+            dynamic: Some(DynamicResolution {
+                min_scale,
+                max_scale,
+                ..DynamicResolution::default()
+            }),
+            ..Resolution::default()
+        };
+        let mut timer = FrameTimer::default();
+        timer.adapt(10., &mut resolution);
+        timer.adapt(11., &mut resolution);
+        let slow = resolution.scale;
+        timer.last = Some(20.);
+        timer.average = Some(0.);
+        timer.adapt(20.001, &mut resolution);
+        [slow, resolution.scale]
+    }
+
+    #[test]
+    fn limits_in_any_order() {
+        assert_eq!(adapted(0.5, 1.), [1. - STEP, 1.]);
+        assert_eq!(adapted(1., 0.5), [1. - STEP, 1.]);
+        assert_eq!(adapted(0.9, 0.9), [0.9, 0.9]);
+        assert_eq!(adapted(f32::NAN, f32::NAN), [1. - STEP, 1.]);
+        assert_eq!(adapted(f32::NAN, 0.5), [0.5, 0.5]);
+    }
+}
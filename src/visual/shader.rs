@@ -0,0 +1,665 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use crate::visual::generated::gl;
+use crate::visual::generated::gl::types::{GLenum, GLint, GLuint};
+use crate::visual::generated::gl::Gles2;
+use crate::visual::vector::Mat4;
+
+use super::gl::BATCH_SIZE_MAX;
+use super::preprocessor::{preprocess, Expanded};
+
+// Attribute locations of the static VAO, the sprite batch adds its vertex colors
+const ATTRIBUTES: [&str; 4] = ["pos_in", "tex_in", "index_in", "color_in"];
+// Samplers named like this are bound to the texture unit of their index,
+// the shadow map takes the unit after the material textures
+pub(super) const SAMPLERS: [&str; 5] = ["tex", "tex1", "tex2", "tex3", "shadow_map"];
+// Location of the built-in shaders in the source tree, used for hot-reloading in debug builds
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/visual/shaders");
+
+pub(super) struct Program(pub(super) u32);
+struct Shader(u32);
+
+// Shader source text with a name for error messages.
+// Sources with a path are watched for changes in debug builds.
+#[derive(Clone)]
+pub struct Source {
+    pub(super) name: String,
+    pub(super) text: String,
+    pub(super) path: Option<PathBuf>,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Source {
+        Source {
+            name: name.to_string(),
+            text: text.to_string(),
+            path: None,
+        }
+    }
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Source> {
+        let path = path.as_ref();
+        Ok(Source {
+            name: path.display().to_string(),
+            text: fs::read_to_string(path)?,
+            path: Some(path.to_path_buf()),
+        })
+    }
+    // Built-in shader baked into the binary; debug builds prefer the file in the source tree
+    pub(super) fn builtin(name: &str, text: &'static str) -> Source {
+        if cfg!(debug_assertions) {
+            if let Ok(source) = Source::file(Path::new(SHADER_DIR).join(name)) {
+                return source;
+            }
+        }
+        Source::new(name, text)
+    }
+    #[cfg(debug_assertions)]
+    fn reread(&self) -> Option<Source> {
+        let path = self.path.as_ref()?;
+        match fs::read_to_string(path) {
+            Ok(text) => Some(Source {
+                text,
+                ..self.clone()
+            }),
+            Err(err) => {
+                println!("WARNING: Could not read {}: {}", self.name, err);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Preprocess { name: String, log: String },
+    Compile { name: String, log: String },
+    Link { name: String, log: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Preprocess { name, log } => {
+                write!(f, "Could not preprocess shader {}:\n{}", name, log)
+            }
+            ShaderError::Compile { name, log } => {
+                write!(f, "Could not compile shader {}:\n{}", name, log)
+            }
+            ShaderError::Link { name, log } => {
+                write!(f, "Could not link program {}:\n{}", name, log)
+            }
+        }
+    }
+}
+
+impl Program {
+    fn new(
+        gl: &Gles2,
+        vert_src: &Expanded,
+        frag_src: &Expanded,
+        attrib_locations: &[&'static str],
+    ) -> Result<Program, ShaderError> {
+        let mut vert = Shader::new(gl, vert_src, gl::VERTEX_SHADER)?;
+        let mut frag = match Shader::new(gl, frag_src, gl::FRAGMENT_SHADER) {
+            Ok(frag) => frag,
+            Err(err) => {
+                Shader::drop_with_gl(&mut vert, gl);
+                return Err(err);
+            }
+        };
+        let handle = unsafe { gl.CreateProgram() };
+
+        for (i, name) in attrib_locations.iter().enumerate() {
+            let name_c = CString::new(*name).unwrap();
+            unsafe {
+                gl.BindAttribLocation(handle, i as GLuint, name_c.as_ptr());
+            }
+        }
+
+        let mut status = 0;
+        unsafe {
+            gl.AttachShader(handle, vert.0);
+            gl.AttachShader(handle, frag.0);
+            gl.LinkProgram(handle);
+            gl.DetachShader(handle, vert.0);
+            gl.DetachShader(handle, frag.0);
+            gl.GetProgramiv(handle, gl::LINK_STATUS, &mut status);
+        }
+
+        Shader::drop_with_gl(&mut frag, gl);
+        Shader::drop_with_gl(&mut vert, gl);
+
+        let program = Program(handle);
+        if status == gl::FALSE as GLint {
+            let log = info_log(gl, handle, false);
+            program.drop_with_gl(gl);
+            return Err(ShaderError::Link {
+                name: format!("{} + {}", vert_src.name, frag_src.name),
+                log,
+            });
+        }
+        Ok(program)
+    }
+    // Queries all active uniforms or attributes, names of arrays are stored without "[0]"
+    fn reflect(&self, gl: &Gles2, uniforms: bool) -> HashMap<String, Variable> {
+        let (count_name, max_len_name) = if uniforms {
+            (gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH)
+        } else {
+            (gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH)
+        };
+        let mut count = 0;
+        let mut max_len = 0;
+        unsafe {
+            gl.GetProgramiv(self.0, count_name, &mut count);
+            gl.GetProgramiv(self.0, max_len_name, &mut max_len);
+        }
+        let mut variables = HashMap::new();
+        let mut name = vec![0u8; max_len.max(1) as usize];
+        for index in 0..count as GLuint {
+            let mut len = 0;
+            let mut size = 0;
+            let mut ty = 0;
+            let location = unsafe {
+                if uniforms {
+                    gl.GetActiveUniform(
+                        self.0,
+                        index,
+                        name.len() as i32,
+                        &mut len,
+                        &mut size,
+                        &mut ty,
+                        name.as_mut_ptr() as *mut _,
+                    );
+                    gl.GetUniformLocation(self.0, name.as_ptr() as *const _)
+                } else {
+                    gl.GetActiveAttrib(
+                        self.0,
+                        index,
+                        name.len() as i32,
+                        &mut len,
+                        &mut size,
+                        &mut ty,
+                        name.as_mut_ptr() as *mut _,
+                    );
+                    gl.GetAttribLocation(self.0, name.as_ptr() as *const _)
+                }
+            };
+            let full_name = String::from_utf8_lossy(&name[..len as usize]);
+            let short_name = full_name.trim_end_matches("[0]").to_string();
+            variables.insert(
+                short_name,
+                Variable {
+                    location,
+                    ty: VariableType::from_gl(ty),
+                    size: size as usize,
+                },
+            );
+        }
+        variables
+    }
+    pub(super) fn drop_with_gl(&self, gl: &Gles2) {
+        unsafe {
+            gl.DeleteProgram(self.0);
+        }
+    }
+}
+
+impl Shader {
+    fn new(gl: &Gles2, src: &Expanded, shader_type: u32) -> Result<Shader, ShaderError> {
+        let handle = unsafe { gl.CreateShader(shader_type) };
+        let mut status = 0;
+        unsafe {
+            gl.ShaderSource(
+                handle,
+                1,
+                &[src.text.as_ptr()] as *const *const u8 as *const *const _,
+                &[src.text.len() as GLint] as *const _,
+            );
+            gl.CompileShader(handle);
+            gl.GetShaderiv(handle, gl::COMPILE_STATUS, &mut status);
+        }
+        let mut shader = Shader(handle);
+        if status == gl::FALSE as GLint {
+            let log = map_log(&info_log(gl, handle, true), src);
+            Shader::drop_with_gl(&mut shader, gl);
+            return Err(ShaderError::Compile {
+                name: src.name.clone(),
+                log,
+            });
+        }
+        Ok(shader)
+    }
+    fn drop_with_gl(&mut self, gl: &Gles2) {
+        unsafe {
+            gl.DeleteShader(self.0);
+        }
+    }
+}
+
+// Rewrites references like "0:12" or "0(12)" in a driver info log to "name:line"
+fn map_log(log: &str, src: &Expanded) -> String {
+    log.lines()
+        .map(|line| {
+            for (start, _) in line.match_indices('0') {
+                let rest = &line[start + 1..];
+                if !(rest.starts_with(':') || rest.starts_with('(')) {
+                    continue;
+                }
+                if start > 0 && line.as_bytes()[start - 1].is_ascii_alphanumeric() {
+                    continue;
+                }
+                let digits = rest[1..].chars().take_while(char::is_ascii_digit).count();
+                if let Ok(number) = rest[1..1 + digits].parse() {
+                    let (name, number) = src.locate(number);
+                    let suffix = &rest[1 + digits..];
+                    let suffix = suffix.strip_prefix(')').unwrap_or(suffix);
+                    return format!("{}{}:{}{}", &line[..start], name, number, suffix);
+                }
+            }
+            line.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn info_log(gl: &Gles2, handle: u32, shader: bool) -> String {
+    let mut len = 0;
+    unsafe {
+        if shader {
+            gl.GetShaderiv(handle, gl::INFO_LOG_LENGTH, &mut len);
+        } else {
+            gl.GetProgramiv(handle, gl::INFO_LOG_LENGTH, &mut len);
+        }
+    }
+    let mut log = vec![0u8; len.max(1) as usize];
+    let mut written = 0;
+    unsafe {
+        if shader {
+            gl.GetShaderInfoLog(handle, len, &mut written, log.as_mut_ptr() as *mut _);
+        } else {
+            gl.GetProgramInfoLog(handle, len, &mut written, log.as_mut_ptr() as *mut _);
+        }
+    }
+    log.truncate(written as usize);
+    String::from_utf8_lossy(&log).trim_end().to_string()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VariableType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Int,
+    IVec2,
+    IVec3,
+    IVec4,
+    Bool,
+    Mat2,
+    Mat3,
+    Mat4,
+    Sampler2D,
+    SamplerCube,
+    Unknown(GLenum),
+}
+
+impl VariableType {
+    fn from_gl(ty: GLenum) -> VariableType {
+        match ty {
+            gl::FLOAT => VariableType::Float,
+            gl::FLOAT_VEC2 => VariableType::Vec2,
+            gl::FLOAT_VEC3 => VariableType::Vec3,
+            gl::FLOAT_VEC4 => VariableType::Vec4,
+            gl::INT => VariableType::Int,
+            gl::INT_VEC2 => VariableType::IVec2,
+            gl::INT_VEC3 => VariableType::IVec3,
+            gl::INT_VEC4 => VariableType::IVec4,
+            gl::BOOL => VariableType::Bool,
+            gl::FLOAT_MAT2 => VariableType::Mat2,
+            gl::FLOAT_MAT3 => VariableType::Mat3,
+            gl::FLOAT_MAT4 => VariableType::Mat4,
+            gl::SAMPLER_2D => VariableType::Sampler2D,
+            gl::SAMPLER_CUBE => VariableType::SamplerCube,
+            ty => VariableType::Unknown(ty),
+        }
+    }
+}
+
+// An active uniform or attribute of a linked program
+#[derive(Clone, Copy, Debug)]
+pub struct Variable {
+    pub location: GLint,
+    pub ty: VariableType,
+    // number of elements for arrays, 1 otherwise
+    pub size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    // also used for booleans
+    Int(i32),
+    Mat4([f32; 16]),
+}
+
+impl UniformValue {
+    fn matches(&self, ty: VariableType) -> bool {
+        matches!(
+            (self, ty),
+            (UniformValue::Float(_), VariableType::Float)
+                | (UniformValue::Vec2(_), VariableType::Vec2)
+                | (UniformValue::Vec3(_), VariableType::Vec3)
+                | (UniformValue::Vec4(_), VariableType::Vec4)
+                | (UniformValue::Int(_), VariableType::Int)
+                | (UniformValue::Int(_), VariableType::Bool)
+                | (UniformValue::Mat4(_), VariableType::Mat4)
+        )
+    }
+    pub(super) fn apply(&self, gl: &Gles2, location: GLint) {
+        unsafe {
+            match self {
+                UniformValue::Float(x) => gl.Uniform1f(location, *x),
+                UniformValue::Vec2([x, y]) => gl.Uniform2f(location, *x, *y),
+                UniformValue::Vec3([x, y, z]) => gl.Uniform3f(location, *x, *y, *z),
+                UniformValue::Vec4([x, y, z, w]) => gl.Uniform4f(location, *x, *y, *z, *w),
+                UniformValue::Int(x) => gl.Uniform1i(location, *x),
+                UniformValue::Mat4(m) => gl.UniformMatrix4fv(location, 1, gl::FALSE, m.as_ptr()),
+            }
+        }
+    }
+}
+
+impl From<Mat4> for UniformValue {
+    fn from(mat: Mat4) -> UniformValue {
+        UniformValue::Mat4(*mat.as_array())
+    }
+}
+
+#[derive(Debug)]
+pub enum UniformError {
+    UnknownName(String),
+    TypeMismatch {
+        name: String,
+        expected: VariableType,
+        found: UniformValue,
+    },
+}
+
+impl fmt::Display for UniformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UniformError::UnknownName(name) => write!(f, "No active uniform named \"{}\"", name),
+            UniformError::TypeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Uniform \"{}\" has type {:?}, but {:?} was given",
+                name, expected, found
+            ),
+        }
+    }
+}
+
+// A linked program together with the locations of the uniforms set by the engine.
+// Programs are expected to use the attributes and uniform names of the default shaders
+// where they need them; uniforms which are not present are simply not set.
+#[derive(Clone)]
+pub struct ShaderProgram(pub(super) Rc<ProgramInternal>);
+
+pub(super) struct ProgramInternal {
+    // compiled variants by define set, the first one is the base variant without defines
+    variants: Cell<Vec<Variant>>,
+    // all files the sources consist of, only read when hot-reloading in debug builds
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    dependencies: Cell<Vec<PathBuf>>,
+
+    vert_src: Source,
+    frag_src: Source,
+    // latest modification time of the source files, see dependencies
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    modified: Cell<Option<SystemTime>>,
+}
+
+struct Variant {
+    // sorted and without duplicates
+    defines: Vec<String>,
+    // None if compiling failed, the base variant is used instead
+    linked: Option<Rc<Linked>>,
+}
+
+pub(super) struct Linked {
+    pub(super) program: Program,
+    uniforms: HashMap<String, Variable>,
+    attributes: HashMap<String, Variable>,
+
+    pub(super) camera: GLint,
+    pub(super) light: GLint,
+    pub(super) models: GLint,
+    pub(super) time: GLint,
+    pub(super) tint: GLint,
+    pub(super) emissive: GLint,
+    pub(super) uv_scroll: GLint,
+    pub(super) alpha_cutoff: GLint,
+    pub(super) shadow_map: GLint,
+}
+
+impl Linked {
+    // Returns the program and the files it was built from
+    fn new(
+        gl: &Gles2,
+        vert_src: &Source,
+        frag_src: &Source,
+        defines: &[String],
+    ) -> Result<(Linked, Vec<PathBuf>), ShaderError> {
+        let mut defines = defines.to_vec();
+        defines.push(format!("BATCH_SIZE_MAX {}", BATCH_SIZE_MAX));
+        let vert_expanded = preprocess(vert_src, &defines)?;
+        let frag_expanded = preprocess(frag_src, &defines)?;
+        let program = Program::new(gl, &vert_expanded, &frag_expanded, &ATTRIBUTES)?;
+        let uniforms = program.reflect(gl, true);
+        let attributes = program.reflect(gl, false);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        unsafe {
+            gl.UseProgram(program.0); // needed for setting the samplers
+            for (unit, name) in SAMPLERS.iter().enumerate() {
+                gl.Uniform1i(location(name), unit as GLint);
+            }
+        }
+        let linked = Linked {
+            camera: location("camera"),
+            light: location("light"),
+            models: location("models"),
+            time: location("time"),
+            tint: location("tint"),
+            emissive: location("emissive"),
+            uv_scroll: location("uv_scroll"),
+            alpha_cutoff: location("alpha_cutoff"),
+            shadow_map: location("shadow_map"),
+            program,
+            uniforms,
+            attributes,
+        };
+        let mut dependencies = vert_expanded.dependencies;
+        dependencies.extend(frag_expanded.dependencies);
+        dependencies.extend(vert_src.path.clone());
+        dependencies.extend(frag_src.path.clone());
+        Ok((linked, dependencies))
+    }
+    // Sets a uniform if the program has it with a matching type
+    pub(super) fn apply_uniform(&self, gl: &Gles2, name: &str, value: &UniformValue) {
+        if let Some(uniform) = self.uniforms.get(name) {
+            if value.matches(uniform.ty) {
+                value.apply(gl, uniform.location);
+            }
+        }
+    }
+}
+
+impl ShaderProgram {
+    pub(super) fn new(
+        gl: &Gles2,
+        vert_src: &Source,
+        frag_src: &Source,
+    ) -> Result<ShaderProgram, ShaderError> {
+        let (linked, dependencies) = Linked::new(gl, vert_src, frag_src, &[])?;
+        Ok(ShaderProgram(Rc::new(ProgramInternal {
+            variants: Cell::new(vec![Variant {
+                defines: Vec::new(),
+                linked: Some(Rc::new(linked)),
+            }]),
+            modified: Cell::new(latest_modification(&dependencies)),
+            dependencies: Cell::new(dependencies),
+            vert_src: vert_src.clone(),
+            frag_src: frag_src.clone(),
+        })))
+    }
+    fn with_variants<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Vec<Variant>) -> R,
+    {
+        let mut variants = self.0.variants.take();
+        let result = f(&mut variants);
+        self.0.variants.set(variants);
+        result
+    }
+    // The base variant without defines
+    pub(super) fn linked(&self) -> Rc<Linked> {
+        self.with_variants(|variants| variants[0].linked.clone().unwrap())
+    }
+    // Returns the variant for this set of defines, compiling it on first use
+    pub(super) fn variant(&self, gl: &Gles2, defines: &[String]) -> Rc<Linked> {
+        let mut defines = defines.to_vec();
+        defines.sort();
+        defines.dedup();
+        self.with_variants(|variants| {
+            let variant = match variants.iter().find(|variant| variant.defines == defines) {
+                Some(variant) => variant,
+                None => {
+                    let linked = match Linked::new(gl, &self.0.vert_src, &self.0.frag_src, &defines)
+                    {
+                        Ok((linked, _)) => Some(Rc::new(linked)),
+                        Err(_err) => {
+                            #[cfg(debug_assertions)]
+                            println!("WARNING: {}", _err);
+                            None
+                        }
+                    };
+                    variants.push(Variant { defines, linked });
+                    variants.last().unwrap()
+                }
+            };
+            variant
+                .linked
+                .clone()
+                .unwrap_or_else(|| variants[0].linked.clone().unwrap())
+        })
+    }
+    // Recompiles all variants if one of the source files changed, the old ones are kept if that fails
+    #[cfg(debug_assertions)]
+    pub(super) fn reload(&self, gl: &Gles2) {
+        let internal = &self.0;
+        let dependencies = internal.dependencies.take();
+        let modified = latest_modification(&dependencies);
+        internal.dependencies.set(dependencies);
+        if modified <= internal.modified.get() {
+            return;
+        }
+        internal.modified.set(modified);
+        let vert_src = internal
+            .vert_src
+            .reread()
+            .unwrap_or_else(|| internal.vert_src.clone());
+        let frag_src = internal
+            .frag_src
+            .reread()
+            .unwrap_or_else(|| internal.frag_src.clone());
+
+        let mut reloaded = Vec::new();
+        let mut dependencies = Vec::new();
+        for defines in self.with_variants(|variants| {
+            variants
+                .iter()
+                .map(|variant| variant.defines.clone())
+                .collect::<Vec<_>>()
+        }) {
+            match Linked::new(gl, &vert_src, &frag_src, &defines) {
+                Ok((linked, variant_dependencies)) => {
+                    dependencies.extend(variant_dependencies);
+                    reloaded.push(Variant {
+                        defines,
+                        linked: Some(Rc::new(linked)),
+                    });
+                }
+                Err(err) => {
+                    println!("WARNING: {}", err);
+                    reloaded
+                        .iter()
+                        .for_each(|variant: &Variant| variant.drop_with_gl(gl));
+                    return;
+                }
+            }
+        }
+        self.drop_with_gl(gl);
+        internal.variants.set(reloaded);
+        dependencies.sort();
+        dependencies.dedup();
+        internal.dependencies.set(dependencies);
+        println!("Reloaded {} + {}", vert_src.name, frag_src.name);
+    }
+    pub(super) fn drop_with_gl(&self, gl: &Gles2) {
+        self.with_variants(|variants| {
+            variants.iter().for_each(|variant| variant.drop_with_gl(gl));
+        });
+    }
+    pub fn uniforms(&self) -> HashMap<String, Variable> {
+        self.linked().uniforms.clone()
+    }
+    pub fn attributes(&self) -> HashMap<String, Variable> {
+        self.linked().attributes.clone()
+    }
+    // Checks that the program has a uniform of this name and type
+    pub fn check_uniform(&self, name: &str, value: &UniformValue) -> Result<(), UniformError> {
+        let linked = self.linked();
+        let uniform = linked
+            .uniforms
+            .get(name)
+            .ok_or_else(|| UniformError::UnknownName(name.to_string()))?;
+        if value.matches(uniform.ty) {
+            Ok(())
+        } else {
+            Err(UniformError::TypeMismatch {
+                name: name.to_string(),
+                expected: uniform.ty,
+                found: value.clone(),
+            })
+        }
+    }
+}
+
+impl Variant {
+    fn drop_with_gl(&self, gl: &Gles2) {
+        if let Some(linked) = &self.linked {
+            linked.program.drop_with_gl(gl);
+        }
+    }
+}
+
+fn latest_modification(paths: &[PathBuf]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .max()
+}
@@ -0,0 +1,19 @@
+#version 100
+
+precision mediump float;
+
+varying vec2 tex_out;
+
+uniform sampler2D tex;
+// texel size along the blur direction, e.g. (1 / width, 0) for a horizontal pass
+uniform vec2 direction;
+
+void main() {
+    // 9 tap gaussian with 5 taps, using bilinear filtering between neighbouring texels
+    vec3 color = texture2D(tex, tex_out).rgb * 0.2270270270;
+    color += texture2D(tex, tex_out + direction * 1.3846153846).rgb * 0.3162162162;
+    color += texture2D(tex, tex_out - direction * 1.3846153846).rgb * 0.3162162162;
+    color += texture2D(tex, tex_out + direction * 3.2307692308).rgb * 0.0702702703;
+    color += texture2D(tex, tex_out - direction * 3.2307692308).rgb * 0.0702702703;
+    gl_FragColor = vec4(color, 1.);
+}
@@ -0,0 +1,20 @@
+#version 100
+
+precision mediump float;
+
+varying vec2 tex_out;
+
+uniform sampler2D tex;
+// size of a texel of tex
+uniform vec2 texel;
+uniform float threshold;
+
+void main() {
+    // box filter over 4x4 texels, the target has half the resolution
+    vec3 color = (texture2D(tex, tex_out + vec2(-texel.x, -texel.y)).rgb
+        + texture2D(tex, tex_out + vec2(texel.x, -texel.y)).rgb
+        + texture2D(tex, tex_out + vec2(-texel.x, texel.y)).rgb
+        + texture2D(tex, tex_out + vec2(texel.x, texel.y)).rgb) * 0.25;
+    float brightness = max(color.r, max(color.g, color.b));
+    gl_FragColor = vec4(color * max(brightness - threshold, 0.) / max(brightness, 0.0001), 1.);
+}
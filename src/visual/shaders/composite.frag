@@ -0,0 +1,53 @@
+#version 100
+
+precision mediump float;
+
+varying vec2 tex_out;
+
+// the scene
+uniform sampler2D tex;
+
+#ifdef BLOOM
+// blurred bright parts of the scene
+uniform sampler2D tex1;
+uniform float bloom_intensity;
+#endif
+
+#ifdef COLOR_GRADING
+// lookup table of size^3 colors, as a strip of size slices along blue with red to the right and green downwards
+uniform sampler2D tex2;
+uniform float lut_size;
+uniform float lut_strength;
+
+vec3 grade(vec3 color) {
+    float slice = color.b * (lut_size - 1.);
+    float slice_low = floor(slice);
+    float slice_high = min(slice_low + 1., lut_size - 1.);
+    vec2 uv = vec2(
+        (color.r * (lut_size - 1.) + 0.5) / (lut_size * lut_size),
+        (color.g * (lut_size - 1.) + 0.5) / lut_size);
+    vec3 low = texture2D(tex2, uv + vec2(slice_low / lut_size, 0.)).rgb;
+    vec3 high = texture2D(tex2, uv + vec2(slice_high / lut_size, 0.)).rgb;
+    return mix(color, mix(low, high, slice - slice_low), lut_strength);
+}
+#endif
+
+#ifdef VIGNETTE
+// intensity and the distance from the center (1 at the corners) where darkening starts
+uniform vec2 vignette;
+#endif
+
+void main() {
+    vec3 color = texture2D(tex, tex_out).rgb;
+#ifdef BLOOM
+    color += texture2D(tex1, tex_out).rgb * bloom_intensity;
+#endif
+#ifdef COLOR_GRADING
+    color = grade(clamp(color, 0., 1.));
+#endif
+#ifdef VIGNETTE
+    float distance = length(tex_out - 0.5) * 1.4142;
+    color *= 1. - vignette.x * smoothstep(vignette.y, 1., distance);
+#endif
+    gl_FragColor = vec4(color, 1.);
+}
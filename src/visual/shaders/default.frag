@@ -6,7 +6,34 @@ varying vec3 pos_out;
 varying vec2 tex_out;
 
 uniform sampler2D tex;
+uniform vec4 tint;
+uniform vec3 emissive;
+uniform float alpha_cutoff;
+
+#ifdef SHADOWS
+#include "shadow.glsl"
+#endif
+#ifdef FOG
+#include "fog.glsl"
+#endif
 
 void main() {
-    gl_FragColor = texture2D(tex, tex_out);
+#ifdef TEXTURED
+    vec4 color = texture2D(tex, tex_out) * tint;
+#else
+    vec4 color = tint;
+#endif
+#ifdef ALPHA_TEST
+    if (color.a < alpha_cutoff) {
+        discard;
+    }
+#endif
+#ifdef SHADOWS
+    color.rgb *= shadow();
+#endif
+    color.rgb += emissive;
+#ifdef FOG
+    color.rgb = apply_fog(color.rgb);
+#endif
+    gl_FragColor = color;
 }
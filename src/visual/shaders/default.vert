@@ -1,17 +1,38 @@
 #version 100
 
+#include "instancing.glsl"
+
 attribute vec4 pos_in;
 attribute vec2 tex_in;
 
 varying vec3 pos_out;
 varying vec2 tex_out;
+#ifdef SHADOWS
+// position in the clip space of the light
+varying vec4 light_out;
+#endif
+#ifdef FOG
+// distance to the camera and height
+varying vec2 fog_out;
+#endif
 
 uniform mat4 camera;
 uniform mat4 light;
-uniform mat4 model;
+uniform float time;
+uniform vec2 uv_scroll;
+#ifdef FOG
+uniform vec3 camera_position;
+#endif
 
 void main() {
     pos_out = pos_in.xyz;
-    tex_out = vec2(tex_in.x, 1. - tex_in.y);
-    gl_Position = camera * model * pos_in;
+    tex_out = vec2(tex_in.x, 1. - tex_in.y) + uv_scroll * time;
+    vec4 world = model_matrix() * pos_in;
+#ifdef SHADOWS
+    light_out = light * world;
+#endif
+#ifdef FOG
+    fog_out = vec2(distance(world.xyz, camera_position), world.y);
+#endif
+    gl_Position = camera * world;
 }
@@ -0,0 +1,20 @@
+// Fog for the FOG variant of the default fragment shader
+
+// distance to the camera and height of the fragment
+varying mediump vec2 fog_out;
+
+uniform lowp vec3 fog_color;
+// start and end of linear fog, or the density of exponential fog
+uniform mediump vec2 fog_range;
+// height below which the fog is full and how fast it thins out above
+uniform mediump vec2 fog_height;
+
+lowp vec3 apply_fog(lowp vec3 color) {
+#ifdef FOG_EXPONENTIAL
+    mediump float amount = 1. - exp(-fog_range.x * fog_out.x);
+#else
+    mediump float amount = clamp((fog_out.x - fog_range.x) / (fog_range.y - fog_range.x), 0., 1.);
+#endif
+    amount *= exp(-max(fog_out.y - fog_height.x, 0.) * fog_height.y);
+    return mix(color, fog_color, amount);
+}
@@ -0,0 +1,39 @@
+#version 100
+
+precision mediump float;
+
+varying vec2 tex_out;
+
+uniform sampler2D tex;
+// size of a texel of tex
+uniform vec2 texel;
+
+#define REDUCE_MIN (1. / 128.)
+#define REDUCE_MUL (1. / 8.)
+#define SPAN_MAX 8.
+
+// Simplified FXAA: blurs along the edge direction estimated from the luma of the diagonal neighbours
+void main() {
+    const vec3 weights = vec3(0.299, 0.587, 0.114);
+    float luma_nw = dot(texture2D(tex, tex_out + vec2(-1., -1.) * texel).rgb, weights);
+    float luma_ne = dot(texture2D(tex, tex_out + vec2(1., -1.) * texel).rgb, weights);
+    float luma_sw = dot(texture2D(tex, tex_out + vec2(-1., 1.) * texel).rgb, weights);
+    float luma_se = dot(texture2D(tex, tex_out + vec2(1., 1.) * texel).rgb, weights);
+    float luma_m = dot(texture2D(tex, tex_out).rgb, weights);
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 direction = vec2(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1. / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, -SPAN_MAX, SPAN_MAX) * texel;
+
+    vec3 inner = 0.5 * (texture2D(tex, tex_out - direction / 6.).rgb
+        + texture2D(tex, tex_out + direction / 6.).rgb);
+    vec3 outer = inner * 0.5 + 0.25 * (texture2D(tex, tex_out - direction * 0.5).rgb
+        + texture2D(tex, tex_out + direction * 0.5).rgb);
+    float luma_outer = dot(outer, weights);
+    gl_FragColor = vec4(luma_outer < luma_min || luma_outer > luma_max ? inner : outer, 1.);
+}
@@ -0,0 +1,8 @@
+// Model matrices of a batch, the alignment slot of every vertex selects one of them
+attribute float index_in;
+
+uniform mat4 models[BATCH_SIZE_MAX];
+
+mat4 model_matrix() {
+    return models[int(index_in)];
+}
@@ -0,0 +1,12 @@
+#version 100
+
+// Fullscreen triangle, see PostChain
+
+attribute vec2 pos_in;
+
+varying vec2 tex_out;
+
+void main() {
+    tex_out = pos_in * 0.5 + 0.5;
+    gl_Position = vec4(pos_in, 0., 1.);
+}
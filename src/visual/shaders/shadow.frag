@@ -0,0 +1,34 @@
+#version 100
+
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+
+varying vec2 tex_out;
+
+uniform sampler2D tex;
+uniform vec4 tint;
+uniform float alpha_cutoff;
+
+// Spreads the depth over the channels with 8 bits each, see unpack_depth in shadow.glsl
+vec4 pack_depth(float depth) {
+    vec4 packed = fract(depth * vec4(1., 255., 65025., 16581375.));
+    return packed - packed.yzww * vec4(1. / 255., 1. / 255., 1. / 255., 0.);
+}
+
+void main() {
+#ifdef ALPHA_TEST
+#ifdef TEXTURED
+    float alpha = texture2D(tex, tex_out).a * tint.a;
+#else
+    float alpha = tint.a;
+#endif
+    if (alpha < alpha_cutoff) {
+        discard;
+    }
+#endif
+    // 1 would wrap around to 0, the cleared map reads as beyond the far plane
+    gl_FragColor = pack_depth(min(gl_FragCoord.z, 0.999999));
+}
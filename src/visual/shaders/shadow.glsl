@@ -0,0 +1,42 @@
+// Shadow map lookup for the SHADOWS variant of the default fragment shader
+
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+#define SHADOW_PRECISION highp
+#else
+#define SHADOW_PRECISION mediump
+#endif
+
+// position in the clip space of the light
+varying SHADOW_PRECISION vec4 light_out;
+
+uniform sampler2D shadow_map;
+// texels per side of the map
+uniform SHADOW_PRECISION float shadow_size;
+uniform SHADOW_PRECISION float shadow_bias;
+uniform lowp float shadow_strength;
+
+SHADOW_PRECISION float unpack_depth(SHADOW_PRECISION vec4 packed) {
+    return dot(packed, vec4(1., 1. / 255., 1. / 65025., 1. / 16581375.));
+}
+
+lowp float shadow_lit(SHADOW_PRECISION vec2 uv, SHADOW_PRECISION float depth) {
+    return step(depth, unpack_depth(texture2D(shadow_map, uv)));
+}
+
+// Light reaching the fragment, the comparisons of the 2x2 nearest texels are interpolated (PCF)
+lowp float shadow() {
+    SHADOW_PRECISION vec3 pos = light_out.xyz / light_out.w * 0.5 + 0.5;
+    if (any(lessThan(pos, vec3(0.))) || any(greaterThan(pos, vec3(1.)))) {
+        return 1.;
+    }
+    SHADOW_PRECISION float depth = pos.z - shadow_bias;
+    SHADOW_PRECISION vec2 texel_pos = pos.xy * shadow_size - 0.5;
+    SHADOW_PRECISION vec2 base = (floor(texel_pos) + 0.5) / shadow_size;
+    SHADOW_PRECISION float texel = 1. / shadow_size;
+    lowp vec2 weight = fract(texel_pos);
+    lowp float lit = mix(
+        mix(shadow_lit(base, depth), shadow_lit(base + vec2(texel, 0.), depth), weight.x),
+        mix(shadow_lit(base + vec2(0., texel), depth), shadow_lit(base + vec2(texel), depth), weight.x),
+        weight.y);
+    return 1. - shadow_strength * (1. - lit);
+}
@@ -0,0 +1,35 @@
+#version 100
+
+precision mediump float;
+
+varying vec3 direction;
+
+#if defined(CUBE_MAP)
+uniform samplerCube tex;
+#elif defined(EQUIRECTANGULAR)
+// longitude to the right, from straight up at the top to straight down at the bottom
+uniform sampler2D tex;
+
+#define PI 3.14159265
+#else
+// gradient by default
+uniform vec3 zenith;
+uniform vec3 horizon;
+uniform vec3 ground;
+#endif
+
+void main() {
+    vec3 dir = normalize(direction);
+#if defined(CUBE_MAP)
+    vec3 color = textureCube(tex, dir).rgb;
+#elif defined(EQUIRECTANGULAR)
+    vec2 uv = vec2(atan(dir.z, dir.x) / (2. * PI) + 0.5, acos(clamp(dir.y, -1., 1.)) / PI);
+    vec3 color = texture2D(tex, uv).rgb;
+#else
+    // steep near the horizon, like the sky fading into haze
+    vec3 color = dir.y > 0.
+        ? mix(horizon, zenith, sqrt(dir.y))
+        : mix(horizon, ground, sqrt(-dir.y));
+#endif
+    gl_FragColor = vec4(color, 1.);
+}
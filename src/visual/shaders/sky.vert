@@ -0,0 +1,14 @@
+#version 100
+
+attribute vec3 pos_in;
+
+varying vec3 direction;
+
+uniform mat4 camera;
+uniform vec3 camera_position;
+
+void main() {
+    direction = pos_in;
+    // centered on the camera, so only its rotation matters, with the depth at infinity behind all geometry
+    gl_Position = (camera * vec4(pos_in + camera_position, 1.)).xyww;
+}
@@ -0,0 +1,28 @@
+#version 100
+#ifdef DISTANCE_FIELD
+#ifdef GL_OES_standard_derivatives
+#extension GL_OES_standard_derivatives : enable
+#endif
+#endif
+
+precision mediump float;
+
+varying vec2 tex_out;
+varying vec4 color_out;
+
+uniform sampler2D tex;
+uniform float smoothing;
+
+void main() {
+    vec4 color = texture2D(tex, tex_out);
+#ifdef DISTANCE_FIELD
+    // the edge is at 0.5, smoothed over about a pixel
+#ifdef GL_OES_standard_derivatives
+    float width = max(0.5 * fwidth(color.a), 0.001);
+#else
+    float width = smoothing;
+#endif
+    color = vec4(1., 1., 1., smoothstep(0.5 - width, 0.5 + width, color.a));
+#endif
+    gl_FragColor = color * color_out;
+}
@@ -0,0 +1,16 @@
+#version 100
+
+attribute vec3 pos_in;
+attribute vec2 tex_in;
+attribute vec4 color_in;
+
+varying vec2 tex_out;
+varying vec4 color_out;
+
+uniform mat4 camera;
+
+void main() {
+    tex_out = tex_in;
+    color_out = color_in;
+    gl_Position = camera * vec4(pos_in, 1.);
+}
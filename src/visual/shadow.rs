@@ -0,0 +1,83 @@
+use crate::visual::generated::gl::Gles2;
+
+use super::gl::{Filter, RenderTarget, Sampler, Wrap};
+use super::shader::{ShaderError, ShaderProgram, Source};
+
+// Shadows cast by the directional light set with Visual::light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shadows {
+    // texels per side of the shadow map, should be a power of two
+    pub map_size: i32,
+    // depth offset against shadow acne, in the range of the light from near (0) to far (1)
+    pub bias: f32,
+    // how much light shadowed surfaces lose, 1 is black
+    pub strength: f32,
+}
+
+impl Default for Shadows {
+    fn default() -> Shadows {
+        Shadows {
+            map_size: 1024,
+            bias: 0.002,
+            strength: 0.6,
+        }
+    }
+}
+
+// Depth of the scene as seen from the light, packed into the RGBA channels since GLES2
+// cannot render into depth textures without OES_depth_texture
+pub(super) struct ShadowMap {
+    // writes the packed depth, the default vertex shader is used
+    pub(super) program: ShaderProgram,
+    pub(super) shadows: Option<Shadows>,
+    // created when first needed, and again when the map size changes
+    target: Option<RenderTarget>,
+}
+
+impl ShadowMap {
+    pub(super) fn new(gl: &Gles2) -> Result<ShadowMap, ShaderError> {
+        let program = ShaderProgram::new(
+            gl,
+            &Source::builtin("default.vert", include_str!("shaders/default.vert")),
+            &Source::builtin("shadow.frag", include_str!("shaders/shadow.frag")),
+        )?;
+        Ok(ShadowMap {
+            program,
+            shadows: None,
+            target: None,
+        })
+    }
+    pub(super) fn configure(&mut self, gl: &Gles2, shadows: Option<Shadows>) {
+        let map_size = shadows.map(|shadows| shadows.map_size);
+        if self.shadows.map(|shadows| shadows.map_size) != map_size {
+            if let Some(target) = self.target.take() {
+                target.drop_with_gl(gl);
+            }
+        }
+        self.shadows = shadows;
+    }
+    // None if shadows are disabled
+    pub(super) fn target(&mut self, gl: &Gles2) -> Option<&RenderTarget> {
+        let size = self.shadows?.map_size.max(1);
+        Some(self.target.get_or_insert_with(|| {
+            // packed depth must not be interpolated
+            let sampler = Sampler {
+                filter: Filter::Nearest,
+                mipmaps: false,
+                wrap: Wrap::Clamp,
+            };
+            RenderTarget::new(gl, size, size, sampler, true, true)
+        }))
+    }
+    // The texture of the map, None if shadows are disabled or it was not drawn yet
+    pub(super) fn texture(&self) -> Option<u32> {
+        self.shadows?;
+        self.target.as_ref().map(|target| target.texture.0)
+    }
+    pub(super) fn drop_with_gl(&mut self, gl: &Gles2) {
+        if let Some(target) = self.target.take() {
+            target.drop_with_gl(gl);
+        }
+        self.program.drop_with_gl(gl);
+    }
+}
@@ -0,0 +1,145 @@
+use std::mem::size_of;
+use std::ptr::null;
+use std::rc::Rc;
+
+use crate::visual::generated::gl;
+use crate::visual::generated::gl::Gles2;
+use crate::visual::vector::{Mat4, Vec3};
+
+use super::gl::{Buffer, CubeMap, Texture, Vao};
+use super::shader::{ShaderError, ShaderProgram, Source, UniformValue};
+
+// Corners of the faces of a cube around the origin, by their bits (z, y, x) set for +1
+const FACES: [[usize; 4]; 6] = [
+    [1, 5, 7, 3],
+    [0, 2, 6, 4],
+    [2, 3, 7, 6],
+    [0, 4, 5, 1],
+    [4, 6, 7, 5],
+    [0, 1, 3, 2],
+];
+
+// Background drawn where no geometry is, see Visual::set_sky. Meant for perspective cameras,
+// orthographic ones only see the part straight ahead.
+#[derive(Clone)]
+pub enum Sky {
+    // e.g. from Visual::load_cube_map
+    CubeMap(Rc<CubeMap>),
+    // panorama spanning 360 degrees horizontally and from straight up to straight down vertically
+    Equirectangular(Rc<Texture>),
+    // colors straight up, at the horizon and straight down
+    Gradient {
+        zenith: [f32; 3],
+        horizon: [f32; 3],
+        ground: [f32; 3],
+    },
+}
+
+// Draws the sky as a cube around the camera
+pub(super) struct SkyBox {
+    program: ShaderProgram,
+    vao: Vao,
+    arrays: Buffer,
+    pub(super) sky: Option<Sky>,
+}
+
+impl SkyBox {
+    pub(super) fn new(gl: &Gles2) -> Result<SkyBox, ShaderError> {
+        let program = ShaderProgram::new(
+            gl,
+            &Source::builtin("sky.vert", include_str!("shaders/sky.vert")),
+            &Source::builtin("sky.frag", include_str!("shaders/sky.frag")),
+        )?;
+        let corner =
+            |index: usize| [0, 1, 2].map(|axis| if index >> axis & 1 == 1 { 1. } else { -1. });
+        let vertices: Vec<f32> = FACES
+            .iter()
+            .flat_map(|[a, b, c, d]| [a, b, c, a, c, d])
+            .flat_map(|index| corner(*index))
+            .collect();
+
+        let vao = Vao::new(gl);
+        let arrays = Buffer::new(gl);
+        unsafe {
+            gl.BindVertexArrayOES(vao.0);
+            gl.BindBuffer(gl::ARRAY_BUFFER, arrays.0);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (vertices.len() * size_of::<f32>()) as isize,
+                vertices.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            gl.EnableVertexAttribArray(0);
+            gl.VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, null());
+        }
+        Ok(SkyBox {
+            program,
+            vao,
+            arrays,
+            sky: None,
+        })
+    }
+    // Draws behind everything in the depth buffer, the program, VAO and texture unit 0 are changed
+    pub(super) fn draw(&self, gl: &Gles2, camera: &Mat4, position: &Vec3) {
+        let sky = match &self.sky {
+            Some(sky) => sky,
+            None => return,
+        };
+        let (linked, uniforms) = match sky {
+            Sky::CubeMap(_) => (
+                self.program.variant(gl, &["CUBE_MAP".to_string()]),
+                Vec::new(),
+            ),
+            Sky::Equirectangular(_) => (
+                self.program.variant(gl, &["EQUIRECTANGULAR".to_string()]),
+                Vec::new(),
+            ),
+            // the base variant
+            Sky::Gradient {
+                zenith,
+                horizon,
+                ground,
+            } => (
+                self.program.linked(),
+                vec![
+                    ("zenith", UniformValue::Vec3(*zenith)),
+                    ("horizon", UniformValue::Vec3(*horizon)),
+                    ("ground", UniformValue::Vec3(*ground)),
+                ],
+            ),
+        };
+        unsafe {
+            gl.UseProgram(linked.program.0);
+            gl.UniformMatrix4fv(linked.camera, 1, gl::FALSE, camera.as_ptr());
+            gl.ActiveTexture(gl::TEXTURE0);
+            match sky {
+                Sky::CubeMap(cube_map) => gl.BindTexture(gl::TEXTURE_CUBE_MAP, cube_map.0),
+                Sky::Equirectangular(texture) => gl.BindTexture(gl::TEXTURE_2D, texture.0),
+                Sky::Gradient { .. } => {}
+            }
+        }
+        let position = UniformValue::Vec3(*position.as_array());
+        linked.apply_uniform(gl, "camera_position", &position);
+        for (name, value) in &uniforms {
+            linked.apply_uniform(gl, name, value);
+        }
+        unsafe {
+            // the depth is exactly the cleared value
+            gl.DepthFunc(gl::LEQUAL);
+            gl.DepthMask(gl::FALSE);
+            gl.BindVertexArrayOES(self.vao.0);
+            gl.DrawArrays(gl::TRIANGLES, 0, 36);
+            gl.DepthMask(gl::TRUE);
+            gl.DepthFunc(gl::LESS);
+        }
+    }
+    #[cfg(debug_assertions)]
+    pub(super) fn reload(&self, gl: &Gles2) {
+        self.program.reload(gl);
+    }
+    pub(super) fn drop_with_gl(&mut self, gl: &Gles2) {
+        self.program.drop_with_gl(gl);
+        Buffer::drop_with_gl(&mut self.arrays, gl);
+        Vao::drop_with_gl(&mut self.vao, gl);
+    }
+}
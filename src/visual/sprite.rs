@@ -0,0 +1,309 @@
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::ptr::null;
+use std::rc::Rc;
+
+use crate::visual::generated::gl;
+use crate::visual::generated::gl::Gles2;
+use crate::visual::vector::{Mat4, Vec3};
+
+use super::camera::{Camera, Projection};
+use super::gl::{Buffer, DrawStats, Texture, Vao};
+use super::shader::{ShaderError, ShaderProgram, Source, UniformValue};
+use super::Spatial;
+
+// Sprites per upload of the vertex buffer, the most that 16 bit indices can address
+const CHUNK_SIZE: usize = 65536 / 4;
+
+// Textured quad in the plane z = 0, queued with Visual::draw_sprite or Visual::draw_sprite_world
+#[derive(Clone)]
+pub struct Sprite {
+    pub texture: Rc<Texture>,
+    pub position: [f32; 2],
+    // radians counterclockwise around the position
+    pub rotation: f32,
+    pub size: [f32; 2],
+    // point of the quad at the position, (0, 0) is the bottom left and (1, 1) the top right corner
+    pub pivot: [f32; 2],
+    // part of the texture as u, v, width and height, with v from the top like the rows of the image
+    pub uv: [f32; 4],
+    pub tint: [f32; 4],
+    // sprites with a higher z are drawn on top, the order of submission decides between equal ones
+    pub z: f32,
+    // the alpha of the texture is a distance field with the edge at 0.5, e.g. of a font, smoothed over
+    // this range of alpha where the driver cannot measure it per pixel (OES_standard_derivatives)
+    pub distance_field: Option<f32>,
+}
+
+impl Sprite {
+    // The whole texture as a quad of size 1 centered on the origin
+    pub fn new(texture: Rc<Texture>) -> Sprite {
+        Sprite {
+            texture,
+            position: [0.; 2],
+            rotation: 0.,
+            size: [1.; 2],
+            pivot: [0.5; 2],
+            uv: [0., 0., 1., 1.],
+            tint: [1.; 4],
+            z: 0.,
+            distance_field: None,
+        }
+    }
+    // Moved out of the plane by the transform if there is one
+    fn vertices(&self, transform: Option<&Mat4>) -> [SpriteVertex; 4] {
+        let [x, y] = self.position;
+        let [width, height] = self.size;
+        let [pivot_x, pivot_y] = self.pivot;
+        let (sin, cos) = self.rotation.sin_cos();
+        let [u, v, uv_width, uv_height] = self.uv;
+        let color = self
+            .tint
+            .map(|channel| (channel.clamp(0., 1.) * 255.).round() as u8);
+        // counterclockwise from the bottom left
+        [(0., 0.), (1., 0.), (1., 1.), (0., 1.)].map(|(corner_x, corner_y)| {
+            let local_x = (corner_x - pivot_x) * width;
+            let local_y = (corner_y - pivot_y) * height;
+            let pos = [
+                x + local_x * cos - local_y * sin,
+                y + local_x * sin + local_y * cos,
+                0.,
+            ];
+            SpriteVertex {
+                pos: match transform {
+                    Some(transform) => *transform.transform(&Vec3::new(pos)).as_array(),
+                    None => pos,
+                },
+                tex: [u + corner_x * uv_width, v + (1. - corner_y) * uv_height],
+                color,
+            }
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SpriteVertex {
+    pos: [f32; 3],
+    tex: [f32; 2],
+    color: [u8; 4],
+}
+
+// Draws the queued sprites into the scene and on top of it, consecutive sprites sharing a texture with one call
+pub(super) struct SpriteBatch {
+    program: ShaderProgram,
+    vao: Vao,
+    // rewritten every frame
+    arrays: Buffer,
+    // two triangles for every sprite of a chunk, never changes
+    elements: Buffer,
+    pub(super) camera: Camera,
+    // drawn on top of everything with the camera above
+    sprites: Vec<(Sprite, Option<Mat4>)>,
+    // drawn with the main camera, with their transforms into the world
+    world: Vec<(Sprite, Option<Mat4>)>,
+    // kept to reuse the allocation
+    vertices: Vec<SpriteVertex>,
+}
+
+impl SpriteBatch {
+    pub(super) fn new(gl: &Gles2) -> Result<SpriteBatch, ShaderError> {
+        let program = ShaderProgram::new(
+            gl,
+            &Source::builtin("sprite.vert", include_str!("shaders/sprite.vert")),
+            &Source::builtin("sprite.frag", include_str!("shaders/sprite.frag")),
+        )?;
+        let elements_data: Vec<u16> = (0..CHUNK_SIZE as u16)
+            .flat_map(|sprite| [0, 1, 2, 0, 2, 3].map(|index| sprite * 4 + index))
+            .collect();
+
+        let vao = Vao::new(gl);
+        let arrays = Buffer::new(gl);
+        let elements = Buffer::new(gl);
+        let stride = size_of::<SpriteVertex>() as i32;
+        unsafe {
+            gl.BindVertexArrayOES(vao.0);
+            gl.BindBuffer(gl::ARRAY_BUFFER, arrays.0);
+            gl.EnableVertexAttribArray(0);
+            gl.EnableVertexAttribArray(1);
+            gl.EnableVertexAttribArray(3);
+            gl.VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, null());
+            gl.VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, 12 as *const c_void);
+            gl.VertexAttribPointer(
+                3,
+                4,
+                gl::UNSIGNED_BYTE,
+                gl::TRUE,
+                stride,
+                20 as *const c_void,
+            );
+            gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, elements.0);
+            gl.BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                (elements_data.len() * size_of::<u16>()) as isize,
+                elements_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+        }
+
+        // pixels from the bottom left of the screen
+        let camera = Camera::new();
+        camera.clear(None, false);
+        camera.projection(
+            &Spatial::new(),
+            Projection::PixelPerfect { near: -1., far: 1. },
+        );
+        Ok(SpriteBatch {
+            program,
+            vao,
+            arrays,
+            elements,
+            camera,
+            sprites: Vec::new(),
+            world: Vec::new(),
+            vertices: Vec::new(),
+        })
+    }
+    pub(super) fn queue(&mut self, sprite: Sprite) {
+        self.sprites.push((sprite, None));
+    }
+    pub(super) fn queue_world(&mut self, sprite: Sprite, transform: Mat4) {
+        self.world.push((sprite, Some(transform)));
+    }
+    // Draws and clears the queue on top of everything, into the framebuffer of this size
+    pub(super) fn draw(&mut self, gl: &Gles2, size: (i32, i32), stats: &mut DrawStats) {
+        let mut sprites = std::mem::take(&mut self.sprites);
+        if let Some(camera) = viewport(gl, &self.camera, size, &sprites) {
+            unsafe {
+                gl.Disable(gl::DEPTH_TEST);
+            }
+            self.draw_sprites(gl, &camera, &mut sprites, stats);
+            unsafe {
+                gl.Enable(gl::DEPTH_TEST);
+            }
+        }
+        sprites.clear();
+        self.sprites = sprites;
+    }
+    // Draws and clears the world queue with the camera, hidden by the scene without writing the depth
+    pub(super) fn draw_world(
+        &mut self,
+        gl: &Gles2,
+        camera: &Camera,
+        size: (i32, i32),
+        stats: &mut DrawStats,
+    ) {
+        let mut sprites = std::mem::take(&mut self.world);
+        if let Some(camera) = viewport(gl, camera, size, &sprites) {
+            unsafe {
+                gl.DepthMask(gl::FALSE);
+            }
+            self.draw_sprites(gl, &camera, &mut sprites, stats);
+            unsafe {
+                gl.DepthMask(gl::TRUE);
+            }
+        }
+        sprites.clear();
+        self.world = sprites;
+    }
+    fn draw_sprites(
+        &mut self,
+        gl: &Gles2,
+        camera: &Mat4,
+        sprites: &mut [(Sprite, Option<Mat4>)],
+        stats: &mut DrawStats,
+    ) {
+        // stable, so sprites of equal z stay in the order of submission
+        sprites
+            .sort_by(|(a, _), (b, _)| a.z.partial_cmp(&b.z).unwrap_or(std::cmp::Ordering::Equal));
+
+        unsafe {
+            gl.Enable(gl::BLEND);
+            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl.BindVertexArrayOES(self.vao.0);
+            gl.BindBuffer(gl::ARRAY_BUFFER, self.arrays.0);
+            gl.ActiveTexture(gl::TEXTURE0);
+        }
+        for chunk in sprites.chunks(CHUNK_SIZE) {
+            self.vertices.clear();
+            self.vertices.extend(
+                chunk
+                    .iter()
+                    .flat_map(|(sprite, transform)| sprite.vertices(transform.as_ref())),
+            );
+            unsafe {
+                // a new store every time, so the driver does not have to wait for the previous draws
+                gl.BufferData(
+                    gl::ARRAY_BUFFER,
+                    (self.vertices.len() * size_of::<SpriteVertex>()) as isize,
+                    self.vertices.as_ptr() as *const _,
+                    gl::STREAM_DRAW,
+                );
+            }
+            let mut start = 0;
+            while start < chunk.len() {
+                let first = &chunk[start].0;
+                let count = chunk[start..]
+                    .iter()
+                    .take_while(|(sprite, _)| {
+                        Rc::ptr_eq(&sprite.texture, &first.texture)
+                            && sprite.distance_field == first.distance_field
+                    })
+                    .count();
+                let linked = match first.distance_field {
+                    Some(_) => self.program.variant(gl, &["DISTANCE_FIELD".to_string()]),
+                    None => self.program.linked(),
+                };
+                unsafe {
+                    gl.UseProgram(linked.program.0);
+                    gl.UniformMatrix4fv(linked.camera, 1, gl::FALSE, camera.as_ptr());
+                }
+                if let Some(smoothing) = first.distance_field {
+                    linked.apply_uniform(gl, "smoothing", &UniformValue::Float(smoothing));
+                }
+                unsafe {
+                    gl.BindTexture(gl::TEXTURE_2D, first.texture.0);
+                    gl.DrawElements(
+                        gl::TRIANGLES,
+                        (count * 6) as i32,
+                        gl::UNSIGNED_SHORT,
+                        (start * 6 * size_of::<u16>()) as *const c_void,
+                    );
+                }
+                stats.calls += 1;
+                start += count;
+            }
+        }
+        unsafe {
+            gl.Disable(gl::BLEND);
+        }
+    }
+    #[cfg(debug_assertions)]
+    pub(super) fn reload(&self, gl: &Gles2) {
+        self.program.reload(gl);
+    }
+    pub(super) fn drop_with_gl(&mut self, gl: &Gles2) {
+        self.program.drop_with_gl(gl);
+        Buffer::drop_with_gl(&mut self.arrays, gl);
+        Buffer::drop_with_gl(&mut self.elements, gl);
+        Vao::drop_with_gl(&mut self.vao, gl);
+    }
+}
+
+// Sets the viewport of the camera and returns its matrix, None if there is nothing to draw
+fn viewport(
+    gl: &Gles2,
+    camera: &Camera,
+    size: (i32, i32),
+    sprites: &[(Sprite, Option<Mat4>)],
+) -> Option<Mat4> {
+    if sprites.is_empty() {
+        return None;
+    }
+    let (x, y, width, height) = camera.viewport_in(size);
+    let (matrix, _) = camera.matrix((width, height))?;
+    unsafe {
+        gl.Viewport(x, y, width, height);
+    }
+    Some(matrix)
+}
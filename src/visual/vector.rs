@@ -1,13 +1,25 @@
 // Everything is transposed because OpenGL is weird in this regard
 
+#[derive(Clone, Debug)]
 pub struct Vec3([f32; 3]);
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Mat4([f32; 16]);
+// Planes (a, b, c, d) with a * x + b * y + c * z + d >= 0 for points inside
+pub struct Frustum([[f32; 4]; 6]);
 
 impl Vec3 {
     pub fn new(xyz: [f32; 3]) -> Self {
         Vec3(xyz)
     }
+
+    pub fn as_array(&self) -> &[f32; 3] {
+        &self.0
+    }
+
+    pub fn distance(&self, other: &Vec3) -> f32 {
+        let (Vec3(a), Vec3(b)) = (self, other);
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+    }
 }
 
 impl Mat4 {
@@ -39,6 +51,12 @@ impl Mat4 {
         self.mul(Mat4(trans))
     }
 
+    pub fn scale(self, v: Vec3) -> Self {
+        let Vec3([x, y, z]) = v;
+        let scale = [x, 0., 0., 0., 0., y, 0., 0., 0., 0., z, 0., 0., 0., 0., 1.];
+        self.mul(Mat4(scale))
+    }
+
     pub fn rot_x(self, alpha: f32) -> Self {
         let sin_alpha = f32::sin(alpha);
         let cos_alpha = f32::cos(alpha);
@@ -80,7 +98,73 @@ impl Mat4 {
         ]))
     }
 
+    pub fn orthographic(
+        self,
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    ) -> Self {
+        let sx = 2. / (right - left);
+        let sy = 2. / (top - bottom);
+        let sz = 2. / (near - far);
+        let px = (right + left) / (left - right);
+        let py = (top + bottom) / (bottom - top);
+        let pz = (far + near) / (near - far);
+        self.mul(Mat4([
+            sx, 0., 0., 0., 0., sy, 0., 0., 0., 0., sz, 0., px, py, pz, 1.,
+        ]))
+    }
+
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        let Mat4(m) = self;
+        let Vec3([x, y, z]) = *v;
+        let mut res = [0.; 3];
+        for (i, r) in res.iter_mut().enumerate() {
+            *r = m[i] * x + m[i + 4] * y + m[i + 8] * z + m[i + 12];
+        }
+        Vec3(res)
+    }
+
+    pub fn as_array(&self) -> &[f32; 16] {
+        &self.0
+    }
+
     pub fn as_ptr(&self) -> *const f32 {
         &self.0 as *const [f32; 16] as *const _
     }
 }
+
+impl Frustum {
+    // Extracts the planes from a combined projection and view matrix (Gribb/Hartmann)
+    pub fn new(matrix: &Mat4) -> Self {
+        let Mat4(m) = matrix;
+        let row = |i: usize| [m[i], m[i + 4], m[i + 8], m[i + 12]];
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let mut planes = [[0.; 4]; 6];
+        for j in 0..4 {
+            planes[0][j] = r3[j] + r0[j]; // left
+            planes[1][j] = r3[j] - r0[j]; // right
+            planes[2][j] = r3[j] + r1[j]; // bottom
+            planes[3][j] = r3[j] - r1[j]; // top
+            planes[4][j] = r3[j] + r2[j]; // near
+            planes[5][j] = r3[j] - r2[j]; // far
+        }
+        for plane in &mut planes {
+            let len = (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt();
+            if len > 0. {
+                plane.iter_mut().for_each(|x| *x /= len);
+            }
+        }
+        Frustum(planes)
+    }
+
+    pub fn contains_sphere(&self, center: &Vec3, radius: f32) -> bool {
+        let Vec3([x, y, z]) = *center;
+        self.0
+            .iter()
+            .all(|[a, b, c, d]| a * x + b * y + c * z + d >= -radius)
+    }
+}
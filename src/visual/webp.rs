@@ -1,48 +1,157 @@
 use crate::visual::generated::webp;
 use crate::visual::generated::webp::size_t;
+use crate::visual::image::Image;
 use std::os::raw::c_int;
 
-pub struct WebP {
-    pub data: Vec<u8>,
-    pub width: u32,
-    pub height: u32,
+// Frames of an animated WebP, composed onto the full canvas and always RGBA
+pub struct Animation {
+    pub frames: Vec<Image>,
+    // time in milliseconds at which each frame ends
+    pub timestamps: Vec<u32>,
+    // 0 means looping forever
+    pub loop_count: u32,
 }
 
-impl WebP {
-    pub fn load_rgb(src: &[u8]) -> Option<WebP> {
-        let mut width: i32 = 0;
-        let mut height: i32 = 0;
-
-        let ret = unsafe {
-            webp::WebPGetInfo(
-                src.as_ptr(),
-                src.len() as size_t,
-                &mut width as *mut _,
-                &mut height as *mut _,
-            )
-        };
-        if ret == 0 {
+// Decodes to RGBA if the image has an alpha channel, to RGB otherwise
+pub fn decode(src: &[u8]) -> Option<Image> {
+    let mut features: webp::WebPBitstreamFeatures = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        webp::WebPGetFeaturesInternal(
+            src.as_ptr(),
+            src.len() as size_t,
+            &mut features,
+            webp::WEBP_DECODER_ABI_VERSION as c_int,
+        )
+    };
+    if ret != webp::VP8StatusCode_VP8_STATUS_OK {
+        return None;
+    }
+    if features.has_alpha != 0 {
+        decode_rgba(src)
+    } else {
+        decode_rgb(src)
+    }
+}
+
+pub fn decode_rgb(src: &[u8]) -> Option<Image> {
+    decode_into(src, false)
+}
+
+pub fn decode_rgba(src: &[u8]) -> Option<Image> {
+    decode_into(src, true)
+}
+
+fn decode_into(src: &[u8], alpha: bool) -> Option<Image> {
+    let mut width: i32 = 0;
+    let mut height: i32 = 0;
+
+    let ret = unsafe {
+        webp::WebPGetInfo(
+            src.as_ptr(),
+            src.len() as size_t,
+            &mut width as *mut _,
+            &mut height as *mut _,
+        )
+    };
+    if ret == 0 {
+        return None;
+    }
+
+    let channels = if alpha { 4 } else { 3 };
+    let mut data = vec![0; (width * height * channels) as usize];
+    let decode_into = if alpha {
+        webp::WebPDecodeRGBAInto
+    } else {
+        webp::WebPDecodeRGBInto
+    };
+    let ret = unsafe {
+        decode_into(
+            src.as_ptr(),
+            src.len() as size_t,
+            data.as_mut_ptr(),
+            data.len() as size_t,
+            width as c_int * channels,
+        )
+    };
+    if ret.is_null() {
+        return None;
+    }
+
+    Some(Image {
+        data,
+        width: width as u32,
+        height: height as u32,
+        alpha,
+    })
+}
+
+// Still images are returned as an animation with a single frame
+pub fn decode_animation(src: &[u8]) -> Option<Animation> {
+    let mut options: webp::WebPAnimDecoderOptions = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        webp::WebPAnimDecoderOptionsInitInternal(
+            &mut options,
+            webp::WEBP_DEMUX_ABI_VERSION as c_int,
+        )
+    };
+    if ret == 0 {
+        return None;
+    }
+    options.color_mode = webp::WEBP_CSP_MODE_MODE_RGBA;
+
+    let data = webp::WebPData {
+        bytes: src.as_ptr(),
+        size: src.len() as size_t,
+    };
+    let decoder = unsafe {
+        webp::WebPAnimDecoderNewInternal(&data, &options, webp::WEBP_DEMUX_ABI_VERSION as c_int)
+    };
+    if decoder.is_null() {
+        return None;
+    }
+    let mut info: webp::WebPAnimInfo = unsafe { std::mem::zeroed() };
+    if unsafe { webp::WebPAnimDecoderGetInfo(decoder, &mut info) } == 0 {
+        unsafe { webp::WebPAnimDecoderDelete(decoder) };
+        return None;
+    }
+
+    let size = match (info.canvas_width as usize)
+        .checked_mul(info.canvas_height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+    {
+        Some(size) => size,
+        None => {
+            unsafe { webp::WebPAnimDecoderDelete(decoder) };
             return None;
         }
-
-        let mut data = vec![0; (width * height * 3) as usize];
-        let ret = unsafe {
-            webp::WebPDecodeRGBInto(
-                src.as_ptr(),
-                src.len() as size_t,
-                data.as_mut_ptr(),
-                data.len() as size_t,
-                width as c_int * 3,
-            )
-        };
-        if ret.is_null() {
+    };
+    let mut animation = Animation {
+        frames: Vec::with_capacity(info.frame_count as usize),
+        timestamps: Vec::with_capacity(info.frame_count as usize),
+        loop_count: info.loop_count,
+    };
+    while unsafe { webp::WebPAnimDecoderHasMoreFrames(decoder) } != 0 {
+        let mut buffer: *mut u8 = std::ptr::null_mut();
+        let mut timestamp: c_int = 0;
+        if unsafe { webp::WebPAnimDecoderGetNext(decoder, &mut buffer, &mut timestamp) } == 0 {
+            unsafe { webp::WebPAnimDecoderDelete(decoder) };
             return None;
         }
-
-        Some(WebP {
+        // the buffer is owned by the decoder and overwritten by the next frame
+        let data = unsafe { std::slice::from_raw_parts(buffer, size) }.to_vec();
+        animation.frames.push(Image {
             data,
-            width: width as u32,
-            height: height as u32,
-        })
+            width: info.canvas_width,
+            height: info.canvas_height,
+            alpha: true,
+        });
+        animation.timestamps.push(timestamp as u32);
+    }
+    unsafe { webp::WebPAnimDecoderDelete(decoder) };
+
+    if animation.frames.is_empty() {
+        None
+    } else {
+        Some(animation)
     }
 }